@@ -0,0 +1,167 @@
+// File: src/og_backend/src/assets.rs
+
+use candid::{CandidType, Nat, Principal};
+use flate2::read::GzDecoder;
+use ic_cdk::call;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+
+/// Content encodings requested from standard asset canisters, in order of preference.
+/// The asset canister returns the first encoding it holds for the requested key.
+const ACCEPT_ENCODINGS: [&str; 2] = ["identity", "gzip"];
+
+/// Largest asset the registry will reassemble in memory, in bytes (before and after decoding).
+pub const MAX_ASSET_LENGTH: u64 = 32 * 1024 * 1024;
+
+/// Argument of the standard asset canister's `get` method.
+#[derive(CandidType)]
+struct GetArg {
+    key: String,
+    accept_encodings: Vec<String>,
+}
+
+/// Response of the standard asset canister's `get` method: the first chunk of the encoded asset.
+#[derive(CandidType, Deserialize)]
+struct EncodedAsset {
+    content: Vec<u8>,
+    content_type: String,
+    content_encoding: String,
+    total_length: Nat,
+    sha256: Option<Vec<u8>>,
+}
+
+/// Argument of the standard asset canister's `get_chunk` method.
+#[derive(CandidType)]
+struct GetChunkArg {
+    key: String,
+    content_encoding: String,
+    index: Nat,
+    sha256: Option<Vec<u8>>,
+}
+
+/// Response of the standard asset canister's `get_chunk` method.
+#[derive(CandidType, Deserialize)]
+struct ChunkContent {
+    content: Vec<u8>,
+}
+
+/// Fetches the raw bytes of an asset from an asset canister.
+/// The custom `retrieve(text) -> blob` method is tried first; if it is unavailable or fails,
+/// the standard certified asset canister interface (`get`/`get_chunk`) is used instead,
+/// so stock dfx asset canisters can be read without custom code.
+pub async fn fetch_asset(canister_id: Principal, path: &str) -> Result<Vec<u8>, String> {
+    match retrieve_custom(canister_id, path).await {
+        Ok(content) => Ok(content),
+        Err(custom_err) => fetch_standard(canister_id, path).await.map_err(|standard_err| {
+            format!(
+                "'retrieve' failed: {}; standard 'get' failed: {}",
+                custom_err, standard_err
+            )
+        }),
+    }
+}
+
+/// Calls the standard asset canister's `get` method, which returns the first chunk of the encoded asset.
+async fn get(canister_id: Principal, path: &str, accept_encodings: &[&str]) -> Result<EncodedAsset, String> {
+    let arg = GetArg {
+        key: path.to_string(),
+        accept_encodings: accept_encodings.iter().map(|e| e.to_string()).collect(),
+    };
+    let (asset,): (EncodedAsset,) = call(canister_id, "get", (arg,))
+        .await
+        .map_err(|(code, msg)| format!("Inter-canister call failed with code {:?}: {:?}", code, msg))?;
+    Ok(asset)
+}
+
+/// Calls the asset canister's custom "retrieve" method, which returns the whole asset as a blob.
+async fn retrieve_custom(canister_id: Principal, path: &str) -> Result<Vec<u8>, String> {
+    let result: Result<(Vec<u8>,), _> = call(canister_id, "retrieve", (path,)).await;
+    result.map(|(content,)| content).map_err(|(code, msg)| {
+        format!("Inter-canister call failed with code {:?}: {:?}", code, msg)
+    })
+}
+
+/// Reads an asset through the standard `get`/`get_chunk` interface, reassembling chunked bodies
+/// and decoding gzip-encoded content.
+/// Bodies larger than a single inter-canister response are fetched chunk by chunk; the reassembled
+/// body is checked against the advertised total length and SHA-256 before it is decoded.
+async fn fetch_standard(canister_id: Principal, path: &str) -> Result<Vec<u8>, String> {
+    let asset = get(canister_id, path, &ACCEPT_ENCODINGS).await?;
+    let total_length = u64::try_from(&asset.total_length.0)
+        .map_err(|_| format!("Asset length {} is out of range", asset.total_length))?;
+    if total_length > MAX_ASSET_LENGTH {
+        return Err(format!(
+            "Asset is {} bytes, exceeding the maximum of {} bytes",
+            total_length, MAX_ASSET_LENGTH
+        ));
+    }
+    let mut content = asset.content;
+    let mut index: u64 = 1;
+    while (content.len() as u64) < total_length {
+        let arg = GetChunkArg {
+            key: path.to_string(),
+            content_encoding: asset.content_encoding.clone(),
+            index: Nat::from(index),
+            sha256: asset.sha256.clone(),
+        };
+        let (chunk,): (ChunkContent,) = call(canister_id, "get_chunk", (arg,))
+            .await
+            .map_err(|(code, msg)| {
+                format!("Fetching chunk {} failed with code {:?}: {:?}", index, code, msg)
+            })?;
+        if chunk.content.is_empty() {
+            return Err(format!("Asset canister returned an empty chunk at index {}", index));
+        }
+        content.extend(chunk.content);
+        index += 1;
+    }
+
+    verify_content(&content, total_length, asset.sha256.as_deref())?;
+    decode_content(content, &asset.content_encoding)
+}
+
+/// Checks a reassembled body against the length and SHA-256 advertised by the asset canister.
+fn verify_content(content: &[u8], total_length: u64, sha256: Option<&[u8]>) -> Result<(), String> {
+    if content.len() as u64 != total_length {
+        return Err(format!(
+            "Reassembled asset is {} bytes, expected {}",
+            content.len(),
+            total_length
+        ));
+    }
+    if let Some(expected) = sha256 {
+        let actual = Sha256::digest(content);
+        if actual.as_slice() != expected {
+            return Err(format!(
+                "Asset hash mismatch: expected {}, computed {}",
+                hex::encode(expected),
+                hex::encode(actual)
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Decodes an asset body according to its content encoding.
+fn decode_content(content: Vec<u8>, content_encoding: &str) -> Result<Vec<u8>, String> {
+    match content_encoding {
+        "identity" => Ok(content),
+        "gzip" => {
+            let mut decoded = Vec::new();
+            // Read one byte past the limit so that oversized content is detected rather than truncated.
+            GzDecoder::new(content.as_slice())
+                .take(MAX_ASSET_LENGTH + 1)
+                .read_to_end(&mut decoded)
+                .map_err(|e| format!("Failed to decode gzip content: {}", e))?;
+            if decoded.len() as u64 > MAX_ASSET_LENGTH {
+                return Err(format!(
+                    "Decoded asset exceeds the maximum of {} bytes",
+                    MAX_ASSET_LENGTH
+                ));
+            }
+            Ok(decoded)
+        }
+        other => Err(format!("Unsupported content encoding '{}'", other)),
+    }
+}
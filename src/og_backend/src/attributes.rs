@@ -0,0 +1,316 @@
+// File: src/og_backend/src/attributes.rs
+
+use crate::registry::{collection_graphics, find_collection};
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+use time::{Date, OffsetDateTime};
+
+/// Maximum number of attributes accepted on a single graphic.
+pub const MAX_ATTRIBUTES_PER_GRAPHIC: usize = 64;
+
+/// The typed value of a graphic attribute.
+/// Dates are kept as written in the metadata, either `YYYY-MM-DD` or an RFC 3339 timestamp.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum AttributeValue {
+    Text(String),
+    Number(f64),
+    Date(String),
+    Boolean(bool),
+}
+
+/// A single key/value trait of a graphic, e.g. `Background: Blue`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Attribute {
+    pub key: String,
+    pub value: AttributeValue,
+}
+
+/// A distinct attribute value within a collection and the number of graphics carrying it.
+#[derive(CandidType, Serialize, Clone, Debug)]
+pub struct AttributeValueCount {
+    pub value: AttributeValue,
+    pub count: u64,
+}
+
+/// The distinct values of one attribute key within a collection.
+#[derive(CandidType, Serialize, Clone, Debug)]
+pub struct AttributeSummary {
+    pub key: String,
+    pub values: Vec<AttributeValueCount>,
+}
+
+/// Total order over attribute values, used to group and sort them: by type, then by value.
+struct ValueKey(AttributeValue);
+
+impl ValueKey {
+    fn rank(&self) -> u8 {
+        match self.0 {
+            AttributeValue::Text(_) => 0,
+            AttributeValue::Number(_) => 1,
+            AttributeValue::Date(_) => 2,
+            AttributeValue::Boolean(_) => 3,
+        }
+    }
+}
+
+impl Ord for ValueKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (&self.0, &other.0) {
+            (AttributeValue::Text(a), AttributeValue::Text(b)) => a.cmp(b),
+            (AttributeValue::Number(a), AttributeValue::Number(b)) => a.total_cmp(b),
+            (AttributeValue::Date(a), AttributeValue::Date(b)) => a.cmp(b),
+            (AttributeValue::Boolean(a), AttributeValue::Boolean(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for ValueKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for ValueKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ValueKey {}
+
+fn is_date(value: &str) -> bool {
+    Date::parse(value, format_description!("[year]-[month]-[day]")).is_ok()
+        || OffsetDateTime::parse(value, &Rfc3339).is_ok()
+}
+
+/// Parses one entry of a graphic's `attributes` array.
+/// Entries are objects with a `key` (or `trait_type`) and a `value`; the value type is taken from the
+/// JSON value unless `type` is given. `"type": "date"` marks a string value as a date.
+pub fn parse_attribute(entry: &Value) -> Result<Attribute, String> {
+    let entry = entry.as_object().ok_or("Attribute must be an object")?;
+    let key = entry
+        .get("key")
+        .or_else(|| entry.get("trait_type"))
+        .and_then(|k| k.as_str())
+        .ok_or("Missing required 'key' field in attribute")?;
+    if key.trim().is_empty() {
+        return Err("Attribute 'key' must not be empty".to_string());
+    }
+    let value = entry.get("value").ok_or("Missing required 'value' field in attribute")?;
+    let declared = match entry.get("type") {
+        None => None,
+        Some(Value::String(t)) => Some(t.as_str()),
+        Some(_) => return Err("Attribute 'type' must be a string".to_string()),
+    };
+    let value = match (declared, value) {
+        (None | Some("string"), Value::String(s)) => AttributeValue::Text(s.clone()),
+        (None | Some("number"), Value::Number(n)) => {
+            AttributeValue::Number(n.as_f64().ok_or("Attribute number is out of range")?)
+        }
+        (None | Some("boolean"), Value::Bool(b)) => AttributeValue::Boolean(*b),
+        (Some("date"), Value::String(s)) if is_date(s) => AttributeValue::Date(s.clone()),
+        (Some("date"), _) => {
+            return Err(format!(
+                "Attribute '{}' must be a date (YYYY-MM-DD or RFC 3339)",
+                key
+            ))
+        }
+        (Some(t @ ("string" | "number" | "boolean")), _) => {
+            return Err(format!("Attribute '{}' value does not match its type '{}'", key, t))
+        }
+        (Some(t), _) => return Err(format!("Unsupported attribute type '{}'", t)),
+        (None, _) => {
+            return Err(format!(
+                "Attribute '{}' value must be a string, number or boolean",
+                key
+            ))
+        }
+    };
+    Ok(Attribute {
+        key: key.to_string(),
+        value,
+    })
+}
+
+/// Parses the optional `attributes` array of a graphic. Keys must be unique within a graphic.
+pub fn parse_attributes(graphic: &Value) -> Result<Vec<Attribute>, String> {
+    let entries = match graphic.get("attributes") {
+        None => return Ok(Vec::new()),
+        Some(Value::Array(entries)) => entries,
+        Some(_) => return Err("'attributes' is not an array".to_string()),
+    };
+    if entries.len() > MAX_ATTRIBUTES_PER_GRAPHIC {
+        return Err(format!(
+            "Too many attributes (maximum is {})",
+            MAX_ATTRIBUTES_PER_GRAPHIC
+        ));
+    }
+    let mut keys = HashSet::new();
+    let mut attributes = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let attribute = parse_attribute(entry)?;
+        if !keys.insert(attribute.key.clone()) {
+            return Err(format!("Duplicate attribute key '{}'", attribute.key));
+        }
+        attributes.push(attribute);
+    }
+    Ok(attributes)
+}
+
+/// Lists the distinct attribute keys of a collection's graphics, each with its distinct values
+/// and how many graphics carry them. Keys and values are returned in ascending order.
+pub fn try_fetch_attributes(collection_id: u64) -> Result<Vec<AttributeSummary>, String> {
+    let collection = find_collection(collection_id).ok_or("Collection not found".to_string())?;
+    let mut keys: BTreeMap<String, BTreeMap<ValueKey, u64>> = BTreeMap::new();
+    for graphic in collection_graphics(&collection) {
+        for attribute in graphic.attributes() {
+            *keys
+                .entry(attribute.key.clone())
+                .or_default()
+                .entry(ValueKey(attribute.value.clone()))
+                .or_default() += 1;
+        }
+    }
+    Ok(keys
+        .into_iter()
+        .map(|(key, values)| AttributeSummary {
+            key,
+            values: values
+                .into_iter()
+                .map(|(value, count)| AttributeValueCount { value: value.0, count })
+                .collect(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(entry: Value) -> Result<Attribute, String> {
+        parse_attribute(&entry)
+    }
+
+    fn value_of(entry: Value) -> AttributeValue {
+        parse(entry).expect("attribute should parse").value
+    }
+
+    #[test]
+    fn infers_the_type_from_the_json_value() {
+        assert_eq!(
+            parse(json!({ "key": "Background", "value": "Blue" })),
+            Ok(Attribute {
+                key: "Background".to_string(),
+                value: AttributeValue::Text("Blue".to_string()),
+            })
+        );
+        assert_eq!(value_of(json!({ "key": "Size", "value": 12.5 })), AttributeValue::Number(12.5));
+        assert_eq!(value_of(json!({ "key": "Animated", "value": false })), AttributeValue::Boolean(false));
+    }
+
+    #[test]
+    fn accepts_trait_type_as_the_key() {
+        assert_eq!(parse(json!({ "trait_type": "Eyes", "value": "Green" })).unwrap().key, "Eyes");
+    }
+
+    #[test]
+    fn honours_declared_types() {
+        assert_eq!(
+            value_of(json!({ "key": "Note", "value": "2024-01-01", "type": "string" })),
+            AttributeValue::Text("2024-01-01".to_string())
+        );
+        assert_eq!(value_of(json!({ "key": "Size", "value": 3, "type": "number" })), AttributeValue::Number(3.0));
+        assert_eq!(
+            value_of(json!({ "key": "Animated", "value": true, "type": "boolean" })),
+            AttributeValue::Boolean(true)
+        );
+    }
+
+    #[test]
+    fn parses_dates_only_when_declared() {
+        assert_eq!(
+            value_of(json!({ "key": "Created", "value": "2024-02-29", "type": "date" })),
+            AttributeValue::Date("2024-02-29".to_string())
+        );
+        assert_eq!(
+            value_of(json!({ "key": "Created", "value": "2024-02-29T12:30:00Z", "type": "date" })),
+            AttributeValue::Date("2024-02-29T12:30:00Z".to_string())
+        );
+        assert_eq!(
+            value_of(json!({ "key": "Created", "value": "2024-02-29" })),
+            AttributeValue::Text("2024-02-29".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_dates() {
+        for value in [json!("2023-02-29"), json!("29/02/2024"), json!("2024-02-29 12:30"), json!(20240229)] {
+            assert_eq!(
+                parse(json!({ "key": "Created", "value": value, "type": "date" })),
+                Err("Attribute 'Created' must be a date (YYYY-MM-DD or RFC 3339)".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_values_that_do_not_match_their_type() {
+        assert_eq!(
+            parse(json!({ "key": "Size", "value": "large", "type": "number" })),
+            Err("Attribute 'Size' value does not match its type 'number'".to_string())
+        );
+        assert_eq!(
+            parse(json!({ "key": "Animated", "value": 1, "type": "boolean" })),
+            Err("Attribute 'Animated' value does not match its type 'boolean'".to_string())
+        );
+        assert_eq!(
+            parse(json!({ "key": "Name", "value": false, "type": "string" })),
+            Err("Attribute 'Name' value does not match its type 'string'".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_types_and_values() {
+        assert_eq!(
+            parse(json!({ "key": "Size", "value": 1, "type": "integer" })),
+            Err("Unsupported attribute type 'integer'".to_string())
+        );
+        assert_eq!(
+            parse(json!({ "key": "Size", "value": 1, "type": 1 })),
+            Err("Attribute 'type' must be a string".to_string())
+        );
+        for value in [json!(null), json!([1]), json!({ "a": 1 })] {
+            assert_eq!(
+                parse(json!({ "key": "Size", "value": value })),
+                Err("Attribute 'Size' value must be a string, number or boolean".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_entries() {
+        assert_eq!(parse(json!("Blue")), Err("Attribute must be an object".to_string()));
+        assert_eq!(
+            parse(json!({ "value": "Blue" })),
+            Err("Missing required 'key' field in attribute".to_string())
+        );
+        assert_eq!(
+            parse(json!({ "key": 1, "value": "Blue" })),
+            Err("Missing required 'key' field in attribute".to_string())
+        );
+        assert_eq!(
+            parse(json!({ "key": " ", "value": "Blue" })),
+            Err("Attribute 'key' must not be empty".to_string())
+        );
+        assert_eq!(
+            parse(json!({ "key": "Background" })),
+            Err("Missing required 'value' field in attribute".to_string())
+        );
+    }
+}
@@ -1,9 +1,75 @@
-use candid::Principal;
+
+use crate::memory::{get_memory, Memory, ROLES_MEMORY_ID};
+use candid::{CandidType, Principal};
 use ic_cdk::api::management_canister::main::{canister_status, CanisterIdRecord};
-use ic_cdk::id;
+use ic_cdk::{id, spawn};
+use ic_stable_structures::storable::{Blob, Bound};
+use ic_stable_structures::{StableBTreeMap, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::time::Duration;
 
-/// Checks if the caller is among this canister's controllers.
-pub async fn authenticate_caller(caller_id: Principal) -> Result<(), String> {
+/// How often the cached controller list is refreshed from the management canister.
+const CONTROLLER_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Access levels that can be granted to principals, ordered from least to most privileged.
+/// A principal holding a role also satisfies every lower role. Controllers implicitly hold every role.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// May use the read-only administrative endpoints.
+    Viewer,
+    /// May register, update and unregister collections.
+    Curator,
+    /// May additionally grant and revoke roles.
+    Admin,
+}
+
+impl Storable for Role {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(vec![*self as u8])
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match bytes[0] {
+            0 => Role::Viewer,
+            1 => Role::Curator,
+            2 => Role::Admin,
+            other => panic!("invalid stored role: {}", other),
+        }
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1,
+        is_fixed_size: true,
+    };
+}
+
+/// A principal together with the role granted to it.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RoleAssignment {
+    pub principal: Principal,
+    pub role: Role,
+}
+
+// Role assignments stored in stable memory, keyed by the principal's raw bytes,
+// and the controller list last reported by the management canister (not persisted),
+// or the reason no refresh has succeeded yet.
+thread_local! {
+    static ROLES: RefCell<StableBTreeMap<Blob<29>, Role, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(ROLES_MEMORY_ID)));
+    static CONTROLLERS: RefCell<Result<Vec<Principal>, String>> =
+        RefCell::new(Err("The controller list has not been fetched yet".to_string()));
+}
+
+fn principal_key(principal: &Principal) -> Blob<29> {
+    Blob::try_from(principal.as_slice()).expect("principal is at most 29 bytes long")
+}
+
+/// Fetches this canister's controllers from the management canister and caches them.
+/// This only succeeds if the canister is one of its own controllers. A failure keeps the list of the last
+/// successful refresh; until one succeeds, the failure is kept instead and reported by `cached_controllers`.
+pub async fn refresh_controllers() -> Result<Vec<Principal>, String> {
     // Prepare a record containing this canister's own principal.
     let canister_id_record = CanisterIdRecord {
         canister_id: id(), // `id()` is the principal of *this* canister
@@ -11,17 +77,93 @@ pub async fn authenticate_caller(caller_id: Principal) -> Result<(), String> {
 
     // Call the management canister's canister_status function.
     // It returns a tuple: (CanisterStatusResponse,)
-    let (status,) = canister_status(canister_id_record)
+    let result = canister_status(canister_id_record)
         .await
-        .map_err(|e| format!("Failed to call canister_status: {}", e.1))?;
-
-    // Make sure the caller is in the canister's controllers list.
-    if !status.settings.controllers.contains(&caller_id) {
-        return Err(format!(
-            "Caller {} not in controllers list: {:?}",
-            caller_id, status.settings.controllers
-        ));
+        .map(|(status,)| status.settings.controllers)
+        .map_err(|e| format!("Failed to call canister_status: {}", e.1));
+
+    CONTROLLERS.with(|c| {
+        let mut cached = c.borrow_mut();
+        if result.is_ok() || cached.is_err() {
+            *cached = result.clone();
+        }
+    });
+    result
+}
+
+/// Returns the controller list as of the last successful refresh. It may lag behind controller changes,
+/// so it is only used for listing controllers, never for authorization.
+/// Fails with the reason of the last failure if no refresh has succeeded yet.
+pub fn cached_controllers() -> Result<Vec<Principal>, String> {
+    CONTROLLERS.with(|c| c.borrow().clone())
+}
+
+/// Schedules a controller refresh right away and then periodically.
+/// Must be called from `init` and `post_upgrade`, since timers do not survive upgrades.
+/// Failures are recorded by `refresh_controllers` and surface through `fetch_controllers`.
+pub fn start_controller_refresh() {
+    fn spawn_refresh() {
+        spawn(async {
+            let _ = refresh_controllers().await;
+        });
+    }
+    ic_cdk_timers::set_timer(Duration::ZERO, spawn_refresh);
+    ic_cdk_timers::set_timer_interval(CONTROLLER_REFRESH_INTERVAL, spawn_refresh);
+}
+
+/// Checks if the caller is among this canister's controllers.
+/// Only the `is_controller` system API is consulted: the cached controller list may still contain
+/// controllers removed since the last refresh, and is only used for listing controllers.
+pub fn authenticate_caller(caller_id: Principal) -> Result<(), String> {
+    if ic_cdk::api::is_controller(&caller_id) {
+        Ok(())
+    } else {
+        Err(format!("Caller {} is not a controller", caller_id))
     }
+}
 
+/// Returns the role granted to the given principal, if any.
+pub fn role_of(principal: &Principal) -> Option<Role> {
+    ROLES.with(|roles| roles.borrow().get(&principal_key(principal)))
+}
+
+/// Checks that the caller holds at least the required role.
+/// Principals without a sufficient stored role are accepted only if they are controllers.
+pub fn authorize(caller_id: Principal, required: Role) -> Result<(), String> {
+    if role_of(&caller_id).is_some_and(|role| role >= required) {
+        return Ok(());
+    }
+    authenticate_caller(caller_id)
+        .map_err(|e| format!("Caller {} lacks the {:?} role: {}", caller_id, required, e))
+}
+
+/// Grants a role to a principal, replacing any role it held before.
+pub fn grant_role(principal: Principal, role: Role) -> Result<(), String> {
+    if principal == Principal::anonymous() {
+        return Err("Roles cannot be granted to the anonymous principal".to_string());
+    }
+    ROLES.with(|roles| roles.borrow_mut().insert(principal_key(&principal), role));
     Ok(())
 }
+
+/// Revokes the role held by a principal.
+pub fn revoke_role(principal: Principal) -> Result<(), String> {
+    ROLES
+        .with(|roles| roles.borrow_mut().remove(&principal_key(&principal)))
+        .map(|_| ())
+        .ok_or_else(|| format!("Principal {} holds no role", principal))
+}
+
+/// Returns every stored role assignment.
+pub fn list_roles() -> Vec<RoleAssignment> {
+    ROLES.with(|roles| {
+        roles
+            .borrow()
+            .iter()
+            .map(|(key, role)| RoleAssignment {
+                principal: Principal::from_slice(key.as_slice()),
+                role,
+            })
+            .collect()
+    })
+}
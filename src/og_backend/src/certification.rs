@@ -0,0 +1,486 @@
+// File: src/og_backend/src/certification.rs
+
+use crate::frontend_api::CollectionInfo;
+use crate::registry::{IndexedCollection, IndexedGraphic};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use candid::{CandidType, Nat};
+use ic_certified_map::{fork, fork_hash, labeled, AsHashTree, Hash, HashTree, RbTree};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::sync::OnceLock;
+
+// Top-level labels of the certified tree.
+const COLLECTIONS_LABEL: &[u8] = b"collections";
+const GRAPHICS_LABEL: &[u8] = b"graphics";
+// Label expected by the HTTP gateway for response certification (version 1).
+const HTTP_ASSETS_LABEL: &[u8] = b"http_assets";
+// Labels of the expression tree of response verification version 2 leading to the list routes
+// (`/api/collections...`), and the label ending an exact expression path.
+const HTTP_EXPR_PREFIX: [&[u8]; 3] = [b"http_expr", b"api", b"collections"];
+const EXACT_LABEL: &[u8] = b"<$>";
+// Certificate expression (response verification version 2) for responses served without certification.
+const SKIP_CERTIFICATION_EXPRESSION: &str = "default_certification(ValidationArgs{no_certification:Empty{}})";
+// Labels of the ICRC-3 tip, which the standard requires at the root of the certified data.
+const LAST_BLOCK_HASH_LABEL: &[u8] = b"last_block_hash";
+const LAST_BLOCK_INDEX_LABEL: &[u8] = b"last_block_index";
+
+/// A query result together with the proof that it is part of the canister's certified state.
+/// `certificate` is the subnet's certificate over the tree's root hash; `witness` is the CBOR-encoded
+/// hash tree revealing the path to the record's leaf, which holds the SHA-256 of the record's JSON body
+/// (see `CertifiedTree`).
+#[derive(CandidType, Serialize, Clone, Debug)]
+pub struct Certified<T> {
+    pub value: T,
+    pub certificate: Vec<u8>,
+    pub witness: Vec<u8>,
+}
+
+/// Certified tree over the registry:
+/// `collections/<id>` and `graphics/<ogid>` (IDs as big-endian u64) map to the SHA-256 of the JSON body of the
+/// `CollectionInfo`/`IndexedGraphic` returned by the certified queries, and `http_assets/<path>` maps to the
+/// SHA-256 of the JSON body served at that path by `http_request`, which is the same body.
+/// The JSON body is compact (no whitespace), lists object fields in the declaration order of the Rust types with the
+/// Candid field names, writes `null` for absent optional fields, principals as text and `nat64` as JSON numbers.
+/// Unlike Candid bytes, whose layout varies between encoders, clients can recompute it from the decoded value.
+/// Like the other indexes it is derived from the stable maps and rebuilt after an upgrade.
+/// The certified data is the hash of `fork(tree, fork(http_expr, tip))`: `http_expr` (see `ExprTree`) lets the
+/// HTTP gateway accept the uncertified list pages, and the tip holds the `last_block_hash` and
+/// `last_block_index` of the ICRC-3 block log. All top-level labels stay in sorted order.
+type CertifiedTree = RbTree<&'static [u8], RbTree<Vec<u8>, Hash>>;
+
+/// Exact-path expressions skipping certification, below `http_expr/api/collections`: `<$>` for the collection list
+/// and `<id>/graphics/<$>` for the graphics list of every registered collection. No wildcard is certified, so the
+/// skip expression can never stand in for the certificate of a single-record route.
+type ExprTree = RbTree<Vec<u8>, SkipEntry>;
+
+/// The expression-path tail certifying that the response at the path is not certified:
+/// `<path...>/<sha256(expression)>` with an empty leaf.
+struct SkipEntry {
+    path: &'static [&'static [u8]],
+}
+
+impl AsHashTree for SkipEntry {
+    fn root_hash(&self) -> Hash {
+        self.as_hash_tree().reconstruct()
+    }
+
+    fn as_hash_tree(&self) -> HashTree<'_> {
+        let skip = labeled(skip_expression_hash(), HashTree::Leaf(Cow::Borrowed(&[])));
+        self.path.iter().rev().fold(skip, |tree, label| labeled(label, tree))
+    }
+}
+
+const COLLECTION_LIST_ENTRY: SkipEntry = SkipEntry { path: &[] };
+const GRAPHICS_LIST_ENTRY: SkipEntry = SkipEntry { path: &[b"graphics", EXACT_LABEL] };
+
+thread_local! {
+    static TREE: RefCell<CertifiedTree> = RefCell::new(empty_tree());
+    static HTTP_EXPR: RefCell<ExprTree> = RefCell::new(empty_expr_tree());
+    /// Index and hash of the last ICRC-3 block, if any block has been appended.
+    static TIP: RefCell<Option<(u64, Hash)>> = const { RefCell::new(None) };
+}
+
+fn empty_tree() -> CertifiedTree {
+    let mut tree = RbTree::default();
+    for label in [COLLECTIONS_LABEL, GRAPHICS_LABEL, HTTP_ASSETS_LABEL] {
+        tree.insert(label, RbTree::default());
+    }
+    tree
+}
+
+fn empty_expr_tree() -> ExprTree {
+    let mut exprs = RbTree::new();
+    exprs.insert(EXACT_LABEL.to_vec(), COLLECTION_LIST_ENTRY);
+    exprs
+}
+
+fn sha256(bytes: &[u8]) -> Hash {
+    Sha256::digest(bytes).into()
+}
+
+/// Path at which `http_request` serves a collection.
+pub fn collection_path(collection_id: u64) -> String {
+    format!("/api/collections/{}", collection_id)
+}
+
+/// Path at which `http_request` serves a graphic.
+pub fn graphic_path(ogid: u64) -> String {
+    format!("/api/graphics/{}", ogid)
+}
+
+/// The JSON body `http_request` serves for a record. Certification hashes exactly these bytes.
+pub fn json_body<T: Serialize>(value: &T) -> Result<Vec<u8>, String> {
+    serde_json::to_vec(value).map_err(|e| e.to_string())
+}
+
+/// The SHA-256 of a record's JSON body, which both its record leaf and its `http_assets` leaf hold.
+fn record_hash<T: Serialize>(value: &T) -> Hash {
+    sha256(&json_body(value).expect("failed to serialize certified record"))
+}
+
+/// Encodes a block index as unsigned LEB128, as ICRC-3 requires for `last_block_index`.
+/// Uses the same encoder as the ICRC-3 hash of `Nat` values, so the two cannot disagree.
+fn leb128(value: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    Nat::from(value).encode(&mut bytes).expect("failed to encode block index");
+    bytes
+}
+
+/// The ICRC-3 part of the certified data: empty until the first block is appended.
+fn tip_tree(tip: Option<(u64, Hash)>) -> HashTree<'static> {
+    match tip {
+        Some((index, hash)) => fork(
+            labeled(LAST_BLOCK_HASH_LABEL, HashTree::Leaf(hash.to_vec().into())),
+            labeled(LAST_BLOCK_INDEX_LABEL, HashTree::Leaf(leb128(index).into())),
+        ),
+        None => HashTree::Empty,
+    }
+}
+
+fn tip_hash() -> Hash {
+    TIP.with(|tip| tip_tree(*tip.borrow()).reconstruct())
+}
+
+fn skip_expression_hash() -> &'static Hash {
+    static HASH: OnceLock<Hash> = OnceLock::new();
+    HASH.get_or_init(|| sha256(SKIP_CERTIFICATION_EXPRESSION.as_bytes()))
+}
+
+/// Returns whether a skip expression is certified for exactly the given request path segments.
+fn is_skip_certified(exprs: &ExprTree, segments: &[&str]) -> bool {
+    match segments {
+        ["api", "collections"] => exprs.get(EXACT_LABEL).is_some(),
+        ["api", "collections", collection_id, "graphics"] => exprs.get(collection_id.as_bytes()).is_some(),
+        _ => false,
+    }
+}
+
+/// Builds a witness of the `http_expr` subtree revealing the expressions of a request path
+/// (a proof of absence for paths that have none).
+fn expr_witness<'a>(exprs: &'a ExprTree, segments: &[&str]) -> HashTree<'a> {
+    let subtree = match segments {
+        ["api", "collections"] => exprs.witness(EXACT_LABEL),
+        ["api", "collections", key, ..] => exprs.witness(key.as_bytes()),
+        _ => HashTree::Pruned(exprs.root_hash()),
+    };
+    HTTP_EXPR_PREFIX.iter().rev().fold(subtree, |tree, label| labeled(label, tree))
+}
+
+fn http_expr_hash() -> Hash {
+    HTTP_EXPR.with(|exprs| expr_witness(&exprs.borrow(), &[]).reconstruct())
+}
+
+/// Publishes the root hash of the registry tree, the expression tree and the ICRC-3 tip as the canister's
+/// certified data.
+fn update_certified_data() {
+    let registry_hash = TREE.with(|tree| tree.borrow().root_hash());
+    let root_hash = fork_hash(&registry_hash, &fork_hash(&http_expr_hash(), &tip_hash()));
+    ic_cdk::api::set_certified_data(&root_hash);
+}
+
+/// Certifies (or re-certifies) a collection and the given graphics.
+pub fn certify_records(collection: &IndexedCollection, graphics: &[IndexedGraphic]) {
+    TREE.with(|tree| {
+        let mut tree = tree.borrow_mut();
+        let id = collection.collection_id;
+        let hash = record_hash(&CollectionInfo::from(collection.clone()));
+        tree.modify(COLLECTIONS_LABEL, |t| t.insert(id.to_be_bytes().to_vec(), hash));
+        tree.modify(HTTP_ASSETS_LABEL, |t| t.insert(collection_path(id).into_bytes(), hash));
+        for graphic in graphics {
+            let hash = record_hash(graphic);
+            tree.modify(GRAPHICS_LABEL, |t| t.insert(graphic.ogid.to_be_bytes().to_vec(), hash));
+            tree.modify(HTTP_ASSETS_LABEL, |t| t.insert(graphic_path(graphic.ogid).into_bytes(), hash));
+        }
+    });
+    HTTP_EXPR.with(|exprs| {
+        exprs.borrow_mut().insert(collection.collection_id.to_string().into_bytes(), GRAPHICS_LIST_ENTRY)
+    });
+    update_certified_data();
+}
+
+/// Removes a collection and the given graphics from the certified tree.
+pub fn remove_records(collection_id: u64, ogids: &[u64]) {
+    TREE.with(|tree| {
+        let mut tree = tree.borrow_mut();
+        tree.modify(COLLECTIONS_LABEL, |t| t.delete(&collection_id.to_be_bytes()));
+        tree.modify(HTTP_ASSETS_LABEL, |t| t.delete(collection_path(collection_id).as_bytes()));
+        for ogid in ogids {
+            tree.modify(GRAPHICS_LABEL, |t| t.delete(&ogid.to_be_bytes()));
+            tree.modify(HTTP_ASSETS_LABEL, |t| t.delete(graphic_path(*ogid).as_bytes()));
+        }
+    });
+    HTTP_EXPR.with(|exprs| exprs.borrow_mut().delete(collection_id.to_string().as_bytes()));
+    update_certified_data();
+}
+
+/// Certifies the index and hash of the last ICRC-3 block.
+pub fn certify_tip(index: u64, hash: Hash) {
+    TIP.with(|tip| *tip.borrow_mut() = Some((index, hash)));
+    update_certified_data();
+}
+
+/// Empties the certified tree and forgets the ICRC-3 tip, ahead of a rebuild.
+pub fn clear() {
+    TREE.with(|tree| *tree.borrow_mut() = empty_tree());
+    HTTP_EXPR.with(|exprs| *exprs.borrow_mut() = empty_expr_tree());
+    TIP.with(|tip| *tip.borrow_mut() = None);
+    update_certified_data();
+}
+
+/// Encodes a witness as self-describing CBOR, the format expected by agents and the HTTP gateway.
+fn encode_witness(witness: &HashTree) -> Vec<u8> {
+    let mut serializer = serde_cbor::ser::Serializer::new(Vec::new());
+    serializer.self_describe().expect("failed to write CBOR tag");
+    witness.serialize(&mut serializer).expect("failed to encode witness");
+    serializer.into_inner()
+}
+
+/// Builds a witness for `label/key` (a proof of presence, or of absence if the key is not certified).
+fn witness(label: &[u8], key: &[u8]) -> Vec<u8> {
+    TREE.with(|tree| {
+        let tree = tree.borrow();
+        encode_witness(&fork(
+            tree.nested_witness(label, |t| t.witness(key)),
+            HashTree::Pruned(fork_hash(&http_expr_hash(), &tip_hash())),
+        ))
+    })
+}
+
+/// Builds a witness revealing the ICRC-3 tip, with the registry tree pruned.
+fn tip_witness() -> Vec<u8> {
+    let registry_hash = TREE.with(|tree| tree.borrow().root_hash());
+    let tip = TIP.with(|tip| *tip.borrow());
+    encode_witness(&fork(
+        HashTree::Pruned(registry_hash),
+        fork(HashTree::Pruned(http_expr_hash()), tip_tree(tip)),
+    ))
+}
+
+/// Returns the certificate and a witness for the ICRC-3 tip, or `None` if no block has been appended yet
+/// or the call is not a query.
+pub fn tip_certificate() -> Option<(Vec<u8>, Vec<u8>)> {
+    if TIP.with(|tip| tip.borrow().is_none()) {
+        return None;
+    }
+    Some((ic_cdk::api::data_certificate()?, tip_witness()))
+}
+
+fn certificate() -> Result<Vec<u8>, String> {
+    ic_cdk::api::data_certificate()
+        .ok_or_else(|| "Certificates are only available in query calls".to_string())
+}
+
+/// Attaches the certificate and a witness for `collections/<id>` to a collection.
+pub fn certify_collection(collection: CollectionInfo) -> Result<Certified<CollectionInfo>, String> {
+    Ok(Certified {
+        certificate: certificate()?,
+        witness: witness(COLLECTIONS_LABEL, &collection.collection_id.to_be_bytes()),
+        value: collection,
+    })
+}
+
+/// Attaches the certificate and a witness for `graphics/<ogid>` to a graphic.
+pub fn certify_graphic(graphic: IndexedGraphic) -> Result<Certified<IndexedGraphic>, String> {
+    Ok(Certified {
+        certificate: certificate()?,
+        witness: witness(GRAPHICS_LABEL, &graphic.ogid.to_be_bytes()),
+        value: graphic,
+    })
+}
+
+/// Returns the `IC-Certificate` header certifying the body served at `path`, if that path is certified.
+pub fn http_certificate_header(path: &str) -> Option<(String, String)> {
+    let certified = TREE.with(|tree| {
+        tree.borrow()
+            .get(HTTP_ASSETS_LABEL)
+            .is_some_and(|t| t.get(path.as_bytes()).is_some())
+    });
+    if !certified {
+        return None;
+    }
+    let certificate = ic_cdk::api::data_certificate()?;
+    Some((
+        "IC-Certificate".to_string(),
+        format!(
+            "certificate=:{}:, tree=:{}:",
+            BASE64.encode(certificate),
+            BASE64.encode(witness(HTTP_ASSETS_LABEL, path.as_bytes()))
+        ),
+    ))
+}
+
+/// Returns the headers with which the HTTP gateway accepts an uncertified response from a list route
+/// (a page, or an error such as an invalid cursor): the skip-certification expression and a version 2
+/// `IC-Certificate` for the exact expression path of the request. Returns no headers for other paths,
+/// and outside query calls, where no certificate is available.
+pub fn http_skip_certification_headers(path: &str) -> Vec<(String, String)> {
+    let segments: Vec<&str> = path.strip_prefix('/').unwrap_or(path).split('/').collect();
+    let witness = HTTP_EXPR.with(|exprs| {
+        let exprs = exprs.borrow();
+        if !is_skip_certified(&exprs, &segments) {
+            return None;
+        }
+        let registry_hash = TREE.with(|tree| tree.borrow().root_hash());
+        Some(encode_witness(&fork(
+            HashTree::Pruned(registry_hash),
+            fork(expr_witness(&exprs, &segments), HashTree::Pruned(tip_hash())),
+        )))
+    });
+    let Some(witness) = witness else {
+        return Vec::new();
+    };
+    let Some(certificate) = ic_cdk::api::data_certificate() else {
+        return Vec::new();
+    };
+    let labels: Vec<&str> = ["http_expr"].into_iter().chain(segments).chain(["<$>"]).collect();
+    let mut expr_path = serde_cbor::ser::Serializer::new(Vec::new());
+    expr_path.self_describe().expect("failed to write CBOR tag");
+    labels.serialize(&mut expr_path).expect("failed to encode expression path");
+    vec![
+        (
+            "IC-CertificateExpression".to_string(),
+            SKIP_CERTIFICATION_EXPRESSION.to_string(),
+        ),
+        (
+            "IC-Certificate".to_string(),
+            format!(
+                "certificate=:{}:, tree=:{}:, expr_path=:{}:, version=2",
+                BASE64.encode(certificate),
+                BASE64.encode(witness),
+                BASE64.encode(expr_path.into_inner())
+            ),
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Lookup {
+        Found,
+        Absent,
+        Unknown,
+    }
+
+    fn flatten_forks<'a, 'b>(tree: &'b HashTree<'a>, nodes: &mut Vec<&'b HashTree<'a>>) {
+        match tree {
+            HashTree::Empty => {}
+            HashTree::Fork(children) => {
+                flatten_forks(&children.0, nodes);
+                flatten_forks(&children.1, nodes);
+            }
+            node => nodes.push(node),
+        }
+    }
+
+    /// Looks a path up in a witness as the HTTP gateway does, telling a proven absence from a pruned branch.
+    fn lookup(tree: &HashTree, path: &[&[u8]]) -> Lookup {
+        let Some((label, rest)) = path.split_first() else {
+            return Lookup::Found;
+        };
+        let mut nodes = Vec::new();
+        flatten_forks(tree, &mut nodes);
+        let labels: Vec<Option<&[u8]>> = nodes
+            .iter()
+            .map(|node| match node {
+                HashTree::Labeled(l, _) => Some(*l),
+                _ => None,
+            })
+            .collect();
+        if let Some(at) = labels.iter().position(|l| *l == Some(*label)) {
+            let HashTree::Labeled(_, subtree) = nodes[at] else { unreachable!() };
+            return lookup(subtree, rest);
+        }
+        let before = |l: &Option<&[u8]>| l.is_some_and(|l| l < *label);
+        let after = |l: &Option<&[u8]>| l.is_some_and(|l| l > *label);
+        let proven = labels.is_empty()
+            || labels.first().is_some_and(after)
+            || labels.last().is_some_and(before)
+            || labels.windows(2).any(|pair| before(&pair[0]) && after(&pair[1]));
+        if proven {
+            Lookup::Absent
+        } else {
+            Lookup::Unknown
+        }
+    }
+
+    fn exprs_with_collections(ids: &[u64]) -> ExprTree {
+        let mut exprs = empty_expr_tree();
+        for id in ids {
+            exprs.insert(id.to_string().into_bytes(), GRAPHICS_LIST_ENTRY);
+        }
+        exprs
+    }
+
+    /// Looks up the skip expression at `expr_path` (below `http_expr`) in a witness of the expression tree.
+    fn lookup_skip(witness: &HashTree, expr_path: &[&str]) -> Lookup {
+        let path: Vec<&[u8]> = ["http_expr"]
+            .iter()
+            .chain(expr_path)
+            .map(|label| label.as_bytes())
+            .chain([skip_expression_hash().as_slice()])
+            .collect();
+        lookup(witness, &path)
+    }
+
+    /// The witness served for a request path, checked against the root hash of the expression tree.
+    fn request_witness<'a>(exprs: &'a ExprTree, request: &[&str]) -> HashTree<'a> {
+        let witness = expr_witness(exprs, request);
+        assert_eq!(witness.reconstruct(), expr_witness(exprs, &[]).reconstruct());
+        witness
+    }
+
+    /// The whole expression tree, revealing every path it certifies.
+    fn full_tree(exprs: &ExprTree) -> HashTree<'_> {
+        HTTP_EXPR_PREFIX.iter().rev().fold(exprs.as_hash_tree(), |tree, label| labeled(label, tree))
+    }
+
+    #[test]
+    fn certifies_skipping_list_routes() {
+        let exprs = exprs_with_collections(&[1, 25]);
+        let list = ["api", "collections"];
+        assert!(is_skip_certified(&exprs, &list));
+        assert_eq!(lookup_skip(&request_witness(&exprs, &list), &["api", "collections", "<$>"]), Lookup::Found);
+        let graphics = ["api", "collections", "25", "graphics"];
+        assert!(is_skip_certified(&exprs, &graphics));
+        let expr_path = ["api", "collections", "25", "graphics", "<$>"];
+        assert_eq!(lookup_skip(&request_witness(&exprs, &graphics), &expr_path), Lookup::Found);
+    }
+
+    #[test]
+    fn never_skips_single_record_routes() {
+        let exprs = exprs_with_collections(&[1, 25]);
+        for request in [
+            vec!["api", "graphics", "1"],
+            vec!["api", "collections", "1"],
+            vec!["api", "collections", "2", "graphics"],
+        ] {
+            assert!(!is_skip_certified(&exprs, &request));
+            // Neither the exact path nor any wildcard covering it may resolve to the skip expression:
+            // the certified tree proves them absent, and the served witness does not contain them.
+            let mut candidates = vec![[request.clone(), vec!["<$>"]].concat()];
+            for len in 0..=request.len() {
+                candidates.push([&request[..len], &["<*>"]].concat());
+            }
+            for candidate in candidates {
+                assert_eq!(lookup_skip(&full_tree(&exprs), &candidate), Lookup::Absent, "{:?}", candidate);
+                assert_ne!(lookup_skip(&request_witness(&exprs, &request), &candidate), Lookup::Found, "{:?}", candidate);
+            }
+        }
+    }
+
+    #[test]
+    fn encodes_block_indexes_as_leb128() {
+        assert_eq!(leb128(0), [0x00]);
+        assert_eq!(leb128(127), [0x7f]);
+        assert_eq!(leb128(128), [0x80, 0x01]);
+        // Example from the LEB128 specification.
+        assert_eq!(leb128(624_485), [0xe5, 0x8e, 0x26]);
+        assert_eq!(leb128(u64::MAX), [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+    }
+}
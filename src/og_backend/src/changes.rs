@@ -0,0 +1,93 @@
+// File: src/og_backend/src/changes.rs
+
+use crate::frontend_api::MAX_PAGE_SIZE;
+use crate::memory::{get_memory, Memory, CHANGES_DATA_MEMORY_ID, CHANGES_INDEX_MEMORY_ID};
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_cdk::api::time;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{StableLog, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+/// What happened to the registry.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum Change {
+    CollectionRegistered { collection_id: u64 },
+    /// The collection's own fields (title, description, artist, ...) changed during an update.
+    CollectionModified { collection_id: u64 },
+    CollectionUnregistered { collection_id: u64 },
+    GraphicAdded { collection_id: u64, ogid: u64 },
+    GraphicModified { collection_id: u64, ogid: u64 },
+    GraphicRemoved { collection_id: u64, ogid: u64 },
+}
+
+/// An entry of the change feed. `seq` numbers are assigned consecutively from 0 and never reused.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ChangeEvent {
+    pub seq: u64,
+    /// Nanoseconds since the epoch.
+    pub timestamp: u64,
+    /// The principal that triggered the change; the registry's own ID for automatic resyncs.
+    pub caller: Principal,
+    pub change: Change,
+}
+
+/// One page of the change feed. Pass `next_seq` to the following `changes_since` call to resume.
+#[derive(CandidType, Serialize, Clone, Debug)]
+pub struct ChangeFeedPage {
+    pub events: Vec<ChangeEvent>,
+    pub next_seq: u64,
+    /// Number of events recorded so far; the feed is exhausted once `next_seq` reaches it.
+    pub tip: u64,
+}
+
+impl Storable for ChangeEvent {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("failed to encode ChangeEvent"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("failed to decode ChangeEvent")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+thread_local! {
+    static CHANGES: RefCell<StableLog<ChangeEvent, Memory, Memory>> = RefCell::new(
+        StableLog::init(get_memory(CHANGES_INDEX_MEMORY_ID), get_memory(CHANGES_DATA_MEMORY_ID))
+            .expect("failed to initialize the change feed"),
+    );
+}
+
+/// Appends the given changes to the feed, all with the same timestamp and caller.
+pub fn record(caller: Principal, changes: impl IntoIterator<Item = Change>) {
+    let timestamp = time();
+    CHANGES.with(|log| {
+        let log = log.borrow();
+        for change in changes {
+            let event = ChangeEvent { seq: log.len(), timestamp, caller, change };
+            log.append(&event).expect("failed to append to the change feed");
+        }
+    });
+}
+
+/// Returns up to `limit` events (at most `MAX_PAGE_SIZE`) with a sequence number of `seq` or higher, oldest first.
+/// Fails if `seq` lies beyond the tip, which means the caller's cursor does not belong to this feed.
+pub fn try_changes_since(seq: u64, limit: Option<u64>) -> Result<ChangeFeedPage, String> {
+    let limit = limit.unwrap_or(MAX_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    CHANGES.with(|log| {
+        let log = log.borrow();
+        let tip = log.len();
+        if seq > tip {
+            return Err(format!("Sequence number {} is beyond the tip of the feed ({})", seq, tip));
+        }
+        let end = seq.saturating_add(limit).min(tip);
+        Ok(ChangeFeedPage {
+            events: (seq..end).filter_map(|i| log.get(i)).collect(),
+            next_seq: end,
+            tip,
+        })
+    })
+}
@@ -0,0 +1,427 @@
+// File: src/og_backend/src/diff.rs
+
+use crate::registry::{
+    collection_differs, collection_graphics, find_collection, graphic_differs, match_old_graphic,
+    parse_proposals, retrieve, CollectionProposal, GraphicProposal, IndexedCollection, IndexedGraphic,
+};
+use crate::attributes::Attribute;
+use crate::error::OgError;
+use crate::media;
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// A collection field whose value would change. Tags are reported as a comma-separated list.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct FieldChange {
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+/// A graphic present in the new metadata but not yet registered.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct AddedGraphic {
+    pub asset: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub attributes: Vec<Attribute>,
+    pub tags: Vec<String>,
+    pub media_type: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub byte_size: Option<u64>,
+    pub content_hash: Option<String>,
+}
+
+/// A registered graphic that is no longer present in the new metadata.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RemovedGraphic {
+    pub ogid: u64,
+    pub asset: String,
+    pub title: String,
+}
+
+/// A registered graphic whose title, description, attributes, tags or asset properties would change.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ModifiedGraphic {
+    pub ogid: u64,
+    pub asset: String,
+    pub old_title: String,
+    pub new_title: String,
+    pub old_description: Option<String>,
+    pub new_description: Option<String>,
+    pub old_attributes: Vec<Attribute>,
+    pub new_attributes: Vec<Attribute>,
+    pub old_tags: Vec<String>,
+    pub new_tags: Vec<String>,
+    pub old_media_type: Option<String>,
+    pub new_media_type: Option<String>,
+    pub old_width: Option<u32>,
+    pub new_width: Option<u32>,
+    pub old_height: Option<u32>,
+    pub new_height: Option<u32>,
+    pub old_byte_size: Option<u64>,
+    pub new_byte_size: Option<u64>,
+    pub old_content_hash: Option<String>,
+    pub new_content_hash: Option<String>,
+}
+
+/// Structured difference between a registered collection and its asset canister's current metadata.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CollectionDiff {
+    pub collection_id: u64,
+    pub collection_changes: Vec<FieldChange>,
+    pub graphics_added: Vec<AddedGraphic>,
+    pub graphics_removed: Vec<RemovedGraphic>,
+    pub graphics_modified: Vec<ModifiedGraphic>,
+}
+
+impl CollectionDiff {
+    /// Returns true if applying the metadata would not change anything.
+    pub fn is_empty(&self) -> bool {
+        self.collection_changes.is_empty()
+            && self.graphics_added.is_empty()
+            && self.graphics_removed.is_empty()
+            && self.graphics_modified.is_empty()
+    }
+}
+
+fn field_change(field: &str, old_value: Option<&String>, new_value: Option<&String>) -> Option<FieldChange> {
+    (old_value != new_value).then(|| FieldChange {
+        field: field.to_string(),
+        old_value: old_value.cloned(),
+        new_value: new_value.cloned(),
+    })
+}
+
+/// Compares a registered collection and its graphics against new proposals.
+/// Graphics are matched by asset path exactly as `index_graphics_differential` matches them.
+pub fn diff_collection(
+    old_collection: &IndexedCollection,
+    old_graphics: &[IndexedGraphic],
+    collection_proposal: &CollectionProposal,
+    graphic_proposals: &[GraphicProposal],
+) -> CollectionDiff {
+    let collection_changes = if collection_differs(old_collection, collection_proposal) {
+        [
+            field_change("title", Some(&old_collection.title), Some(&collection_proposal.title)),
+            field_change(
+                "description",
+                old_collection.description.as_ref(),
+                collection_proposal.description.as_ref(),
+            ),
+            field_change("artist", old_collection.artist.as_ref(), collection_proposal.artist.as_ref()),
+            field_change(
+                "external_link",
+                old_collection.external_link.as_ref(),
+                collection_proposal.external_link.as_ref(),
+            ),
+            field_change(
+                "tags",
+                Some(&old_collection.tags().join(", ")),
+                Some(&collection_proposal.tags.join(", ")),
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut graphics_added = Vec::new();
+    let mut graphics_modified = Vec::new();
+    let mut assigned = HashSet::new();
+    for proposal in graphic_proposals {
+        match match_old_graphic(old_graphics, &proposal.asset, &assigned) {
+            Some(old_g) => {
+                assigned.insert(old_g.ogid);
+                if graphic_differs(old_g, proposal) {
+                    graphics_modified.push(ModifiedGraphic {
+                        ogid: old_g.ogid,
+                        asset: old_g.asset.clone(),
+                        old_title: old_g.title.clone(),
+                        new_title: proposal.title.clone(),
+                        old_description: old_g.description.clone(),
+                        new_description: proposal.description.clone(),
+                        old_attributes: old_g.attributes().to_vec(),
+                        new_attributes: proposal.attributes.clone(),
+                        old_tags: old_g.tags().to_vec(),
+                        new_tags: proposal.tags.clone(),
+                        old_media_type: old_g.media_type.clone(),
+                        new_media_type: proposal.media_type.clone(),
+                        old_width: old_g.width,
+                        new_width: proposal.width,
+                        old_height: old_g.height,
+                        new_height: proposal.height,
+                        old_byte_size: old_g.byte_size,
+                        new_byte_size: proposal.byte_size,
+                        old_content_hash: old_g.content_hash.clone(),
+                        new_content_hash: proposal.content_hash.clone(),
+                    });
+                }
+            }
+            None => graphics_added.push(AddedGraphic {
+                asset: proposal.asset.clone(),
+                title: proposal.title.clone(),
+                description: proposal.description.clone(),
+                attributes: proposal.attributes.clone(),
+                tags: proposal.tags.clone(),
+                media_type: proposal.media_type.clone(),
+                width: proposal.width,
+                height: proposal.height,
+                byte_size: proposal.byte_size,
+                content_hash: proposal.content_hash.clone(),
+            }),
+        }
+    }
+
+    let graphics_removed = old_graphics
+        .iter()
+        .filter(|g| !assigned.contains(&g.ogid))
+        .map(|g| RemovedGraphic {
+            ogid: g.ogid,
+            asset: g.asset.clone(),
+            title: g.title.clone(),
+        })
+        .collect();
+
+    CollectionDiff {
+        collection_id: old_collection.collection_id,
+        collection_changes,
+        graphics_added,
+        graphics_removed,
+        graphics_modified,
+    }
+}
+
+/// Retrieves the asset canister's current metadata and reports how `update_collection`
+/// would change the registered collection, without modifying any state.
+/// Fails with `NotFound` for an unregistered collection, `InvalidArgument` for another asset canister,
+/// `AssetUnavailable` if the metadata cannot be read and `UpdateFailed` if the update would reject it.
+pub async fn try_preview_update(collection_id: u64, canister_id: Principal) -> Result<CollectionDiff, OgError> {
+    let old_collection = find_collection(collection_id).ok_or_else(|| OgError::NotFound {
+        reason: "Collection not registered".to_string(),
+    })?;
+    old_collection
+        .check_source_canister(canister_id)
+        .map_err(|reason| OgError::InvalidArgument { reason })?;
+    let metadata_blob = retrieve(canister_id, old_collection.metadata_path())
+        .await
+        .map_err(|e| OgError::AssetUnavailable {
+            reason: format!("Failed to retrieve metadata: {}", e),
+        })?;
+    let (collection_proposal, mut graphic_proposals) =
+        parse_proposals(&metadata_blob).map_err(|reason| OgError::UpdateFailed { reason })?;
+    media::inspect_graphics(canister_id, &mut graphic_proposals).await;
+    let old_graphics = collection_graphics(&old_collection);
+    Ok(diff_collection(
+        &old_collection,
+        &old_graphics,
+        &collection_proposal,
+        &graphic_proposals,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attributes::AttributeValue;
+
+    fn canister(id: u8) -> Principal {
+        Principal::from_slice(&[id; 10])
+    }
+
+    fn collection(graphics: &[IndexedGraphic]) -> IndexedCollection {
+        IndexedCollection {
+            collection_id: 7,
+            title: "Collection".to_string(),
+            description: None,
+            artist: Some("Artist".to_string()),
+            external_link: None,
+            graphics: graphics.iter().map(|g| g.ogid).collect(),
+            registration_timestamp: "2024-01-01 00:00:00".to_string(),
+            update_timestamp: None,
+            metadata_path: None,
+            tags: Some(vec!["art".to_string()]),
+            canister_id: Some(canister(1)),
+            sync: None,
+        }
+    }
+
+    fn collection_proposal(old: &IndexedCollection) -> CollectionProposal {
+        CollectionProposal {
+            title: old.title.clone(),
+            description: old.description.clone(),
+            artist: old.artist.clone(),
+            external_link: old.external_link.clone(),
+            tags: old.tags().to_vec(),
+        }
+    }
+
+    fn graphic(ogid: u64, asset: &str, title: &str) -> IndexedGraphic {
+        IndexedGraphic {
+            ogid,
+            collection_id: 7,
+            asset: asset.to_string(),
+            title: title.to_string(),
+            description: None,
+            registration_timestamp: "2024-01-01 00:00:00".to_string(),
+            update_timestamp: None,
+            canister_id: canister(1),
+            attributes: None,
+            tags: None,
+            media_type: Some("image/png".to_string()),
+            width: Some(10),
+            height: Some(10),
+            byte_size: Some(100),
+            content_hash: Some("ab".repeat(32)),
+        }
+    }
+
+    /// A proposal identical to the registered graphic.
+    fn proposal(old: &IndexedGraphic) -> GraphicProposal {
+        GraphicProposal {
+            asset: old.asset.clone(),
+            title: old.title.clone(),
+            description: old.description.clone(),
+            attributes: old.attributes().to_vec(),
+            tags: old.tags().to_vec(),
+            media_type: old.media_type.clone(),
+            width: old.width,
+            height: old.height,
+            byte_size: old.byte_size,
+            content_hash: old.content_hash.clone(),
+        }
+    }
+
+    fn diff(old_graphics: &[IndexedGraphic], proposals: &[GraphicProposal]) -> CollectionDiff {
+        let old = collection(old_graphics);
+        diff_collection(&old, old_graphics, &collection_proposal(&old), proposals)
+    }
+
+    #[test]
+    fn unchanged_metadata_is_empty() {
+        let old = [graphic(1, "/1.png", "One"), graphic(2, "/2.png", "Two")];
+        let result = diff(&old, &[proposal(&old[0]), proposal(&old[1])]);
+        assert!(result.is_empty(), "{:?}", result);
+        assert_eq!(result.collection_id, 7);
+    }
+
+    #[test]
+    fn reports_changed_collection_fields_only() {
+        let old = collection(&[]);
+        let mut new = collection_proposal(&old);
+        new.title = "Renamed".to_string();
+        new.description = Some("Now described".to_string());
+        new.tags = vec!["art".to_string(), "ink".to_string()];
+        let result = diff_collection(&old, &[], &new, &[]);
+        let changes: Vec<(&str, Option<&str>, Option<&str>)> = result
+            .collection_changes
+            .iter()
+            .map(|c| (c.field.as_str(), c.old_value.as_deref(), c.new_value.as_deref()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("title", Some("Collection"), Some("Renamed")),
+                ("description", None, Some("Now described")),
+                ("tags", Some("art"), Some("art, ink")),
+            ]
+        );
+    }
+
+    #[test]
+    fn detects_added_and_removed_graphics() {
+        let old = [graphic(1, "/1.png", "One"), graphic(2, "/2.png", "Two")];
+        let mut added = proposal(&graphic(0, "/3.png", "Three"));
+        added.byte_size = None;
+        let result = diff(&old, &[proposal(&old[0]), added]);
+        assert_eq!(result.graphics_added.len(), 1);
+        assert_eq!(result.graphics_added[0].asset, "/3.png");
+        assert_eq!(result.graphics_added[0].title, "Three");
+        assert_eq!(result.graphics_added[0].byte_size, None);
+        assert_eq!(result.graphics_removed.len(), 1);
+        assert_eq!(result.graphics_removed[0].ogid, 2);
+        assert_eq!(result.graphics_removed[0].asset, "/2.png");
+        assert!(result.graphics_modified.is_empty());
+    }
+
+    #[test]
+    fn detects_modified_graphics() {
+        let old = [graphic(1, "/1.png", "One"), graphic(2, "/2.png", "Two")];
+        let mut retitled = proposal(&old[0]);
+        retitled.title = "Uno".to_string();
+        retitled.attributes = vec![Attribute {
+            key: "Size".to_string(),
+            value: AttributeValue::Number(1.0),
+        }];
+        let mut redrawn = proposal(&old[1]);
+        redrawn.content_hash = Some("cd".repeat(32));
+        redrawn.byte_size = Some(200);
+        let result = diff(&old, &[retitled, redrawn]);
+        assert!(result.graphics_added.is_empty() && result.graphics_removed.is_empty());
+        assert_eq!(result.graphics_modified.len(), 2);
+
+        let first = &result.graphics_modified[0];
+        assert_eq!((first.ogid, first.old_title.as_str(), first.new_title.as_str()), (1, "One", "Uno"));
+        assert!(first.old_attributes.is_empty());
+        assert_eq!(first.new_attributes.len(), 1);
+
+        let second = &result.graphics_modified[1];
+        assert_eq!(second.ogid, 2);
+        assert_eq!((second.old_byte_size, second.new_byte_size), (Some(100), Some(200)));
+        assert_ne!(second.old_content_hash, second.new_content_hash);
+    }
+
+    #[test]
+    fn legacy_records_without_attributes_or_tags_are_unchanged() {
+        let old = [graphic(1, "/1.png", "One")];
+        let mut new = proposal(&old[0]);
+        new.attributes = Vec::new();
+        new.tags = Vec::new();
+        assert!(diff(&old, &[new]).is_empty());
+    }
+
+    #[test]
+    fn matches_graphics_by_asset_path_preserving_ogids() {
+        let old = [graphic(11, "/a.png", "A"), graphic(12, "/b.png", "B")];
+        // Reordered, with one graphic retitled: OGIDs follow the asset path, not the position.
+        let mut b = proposal(&old[1]);
+        b.title = "Bee".to_string();
+        let result = diff(&old, &[b, proposal(&old[0])]);
+        assert!(result.graphics_added.is_empty() && result.graphics_removed.is_empty());
+        assert_eq!(result.graphics_modified.len(), 1);
+        assert_eq!(result.graphics_modified[0].ogid, 12);
+        assert_eq!(result.graphics_modified[0].asset, "/b.png");
+    }
+
+    #[test]
+    fn matches_each_registered_graphic_once() {
+        let old = [graphic(11, "/a.png", "A")];
+        // A second proposal for the same asset path cannot reuse the OGID and is reported as added.
+        let result = diff(&old, &[proposal(&old[0]), proposal(&old[0])]);
+        assert!(result.graphics_modified.is_empty() && result.graphics_removed.is_empty());
+        assert_eq!(result.graphics_added.len(), 1);
+        assert_eq!(result.graphics_added[0].asset, "/a.png");
+    }
+
+    #[test]
+    fn rejects_other_asset_canisters() {
+        let old = collection(&[]);
+        assert!(old.check_source_canister(canister(1)).is_ok());
+        assert_eq!(
+            old.check_source_canister(canister(2)),
+            Err(format!(
+                "Collection 7 is registered from asset canister {}, not {}",
+                canister(1),
+                canister(2)
+            ))
+        );
+
+        // Collections without a recorded canister or graphics accept any canister.
+        let legacy = IndexedCollection { canister_id: None, ..old };
+        assert!(legacy.check_source_canister(canister(2)).is_ok());
+    }
+}
@@ -0,0 +1,20 @@
+// File: src/og_backend/src/error.rs
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// Typed error returned by the v2 Candid interface.
+/// Each variant carries a human-readable reason describing what went wrong.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum OgError {
+    Unauthorized { reason: String },
+    NotFound { reason: String },
+    RegisterFailed { reason: String },
+    UpdateFailed { reason: String },
+    UnregisterFailed { reason: String },
+    InvalidArgument { reason: String },
+    /// An asset canister could not be read, e.g. it is unreachable or no longer serves the requested file.
+    AssetUnavailable { reason: String },
+    /// The canister could not serve an otherwise valid request, e.g. a certificate requested outside a query.
+    Internal { reason: String },
+}
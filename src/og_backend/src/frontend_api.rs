@@ -1,11 +1,18 @@
 // File: src/og_backend/src/frontend_api.rs
 
-use crate::registry::{get_state, IndexedGraphic};
-use serde::Serialize;
+use crate::registry::{find_collection, find_graphic, with_index, IndexedCollection, IndexedGraphic};
+use crate::sync::SyncState;
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+/// Number of items returned per page when the request does not specify a limit.
+pub const DEFAULT_PAGE_SIZE: u64 = 25;
+/// Upper bound on the number of items returned per page, keeping responses well below the message size limit.
+pub const MAX_PAGE_SIZE: u64 = 100;
 
 /// A helper struct to return collection information without the graphics vector.
 /// Now that `title` is mandatory, we store it as a `String`.
-#[derive(Serialize)]
+#[derive(CandidType, Serialize)]
 pub struct CollectionInfo {
     pub collection_id: u64,
     pub title: String,
@@ -14,28 +21,17 @@ pub struct CollectionInfo {
     pub external_link: Option<String>,
     pub registration_timestamp: String,
     pub update_timestamp: Option<String>,
+    pub metadata_path: String,
+    pub tags: Vec<String>,
+    pub canister_id: Option<Principal>,
+    pub sync: Option<SyncState>,
 }
 
-/// Returns a vector of all registered collection IDs.
-pub fn try_fetch_collections() -> Result<Vec<u64>, String> {
-    let state = get_state();
-    let ids = state
-        .registered_collections
-        .into_iter()
-        .map(|c| c.collection_id)
-        .collect();
-    Ok(ids)
-}
-
-/// Returns the collection details (excluding the graphics vector) for the given collection_id.
-/// Now that `title` is non-optional, we assign it directly from the `IndexedCollection` record.
-pub fn try_fetch_collection(collection_id: u64) -> Result<CollectionInfo, String> {
-    let state = get_state();
-    state
-        .registered_collections
-        .into_iter()
-        .find(|c| c.collection_id == collection_id)
-        .map(|collection| CollectionInfo {
+impl From<IndexedCollection> for CollectionInfo {
+    fn from(collection: IndexedCollection) -> Self {
+        let metadata_path = collection.metadata_path().to_string();
+        let tags = collection.tags().to_vec();
+        CollectionInfo {
             collection_id: collection.collection_id,
             title: collection.title, // no longer Option
             description: collection.description,
@@ -43,29 +39,183 @@ pub fn try_fetch_collection(collection_id: u64) -> Result<CollectionInfo, String
             external_link: collection.external_link,
             registration_timestamp: collection.registration_timestamp,
             update_timestamp: collection.update_timestamp,
-        })
+            metadata_path,
+            tags,
+            canister_id: collection.canister_id,
+            sync: collection.sync,
+        }
+    }
+}
+
+/// A request for one page of results.
+/// `cursor` is the `next_cursor` returned with the previous page (omit it to start from the beginning),
+/// and `limit` caps the number of items returned (defaults to `DEFAULT_PAGE_SIZE`, at most `MAX_PAGE_SIZE`).
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct PageRequest {
+    pub cursor: Option<u64>,
+    pub limit: Option<u64>,
+}
+
+/// The order in which collections are paged.
+/// Collection IDs are derived from hashes, so only these orders are meaningful to browse by.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum CollectionOrder {
+    /// Oldest registration first.
+    #[default]
+    Registered,
+    /// Newest registration first.
+    Recent,
+    /// By title, ignoring case.
+    Title,
+}
+
+/// One page of results, along with the cursor for the following page and the total number of items.
+/// `next_cursor` is `None` once the last page has been returned.
+#[derive(CandidType, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<u64>,
+    pub total: u64,
+}
+
+/// Slices the requested page out of `items`, which must yield `total` elements.
+/// Cursors past the end yield an empty last page.
+pub fn paginate<T>(items: impl Iterator<Item = T>, total: u64, page: &PageRequest) -> Page<T> {
+    // Saturate before converting: `usize` is 32 bits on wasm32, so larger cursors would wrap around.
+    let offset = page.cursor.unwrap_or(0).min(total);
+    let limit = page.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let items: Vec<T> = items
+        .skip(offset as usize)
+        .take(limit as usize)
+        .collect();
+    let end = offset.saturating_add(items.len() as u64);
+    Page {
+        items,
+        next_cursor: if end < total { Some(end) } else { None },
+        total,
+    }
+}
+
+/// Returns a vector of all registered collection IDs.
+pub fn collection_ids() -> Vec<u64> {
+    with_index(|index| index.collection_ids().collect())
+}
+
+/// Returns one page of registered collection IDs, in the given order.
+pub fn collection_ids_page(page: &PageRequest, order: CollectionOrder) -> Page<u64> {
+    with_index(|index| {
+        let total = index.collection_count();
+        match order {
+            CollectionOrder::Registered => paginate(index.collection_ids_by_registration(), total, page),
+            CollectionOrder::Recent => paginate(index.collection_ids_by_registration().rev(), total, page),
+            CollectionOrder::Title => paginate(index.collection_ids_by_title(), total, page),
+        }
+    })
+}
+
+/// Returns the collection details (excluding the graphics vector) for the given collection_id.
+pub fn try_fetch_collection(collection_id: u64) -> Result<CollectionInfo, String> {
+    find_collection(collection_id)
+        .map(CollectionInfo::from)
         .ok_or_else(|| "Collection not found".to_string())
 }
 
 /// Returns a vector of graphic OGIDs for the collection with the given collection_id.
 pub fn try_fetch_graphics(collection_id: u64) -> Result<Vec<u64>, String> {
-    let state = get_state();
-    state
-        .registered_collections
-        .into_iter()
-        .find(|c| c.collection_id == collection_id)
-        .map(|collection| collection.graphics)
+    with_index(|index| index.graphics_of(collection_id).map(<[u64]>::to_vec))
         .ok_or_else(|| "Collection not found".to_string())
 }
 
+/// Returns one page of graphic OGIDs for the collection with the given collection_id.
+pub fn try_fetch_graphics_page(collection_id: u64, page: &PageRequest) -> Result<Page<u64>, String> {
+    with_index(|index| {
+        index
+            .graphics_of(collection_id)
+            .map(|ogids| paginate(ogids.iter().copied(), ogids.len() as u64, page))
+    })
+    .ok_or_else(|| "Collection not found".to_string())
+}
+
 /// Returns all the fields of a registered graphic for the given OGID.
 /// The `IndexedGraphic` struct itself has been updated to require `title: String`.
 pub fn try_fetch_graphic(ogid: u64) -> Result<IndexedGraphic, String> {
-    let state = get_state();
-    state
-        .registered_graphics
-        .into_iter()
-        .find(|g| g.ogid == ogid)
-        .ok_or_else(|| "Graphic not found".to_string())
+    find_graphic(ogid).ok_or_else(|| "Graphic not found".to_string())
+}
+
+/// Returns one page of full graphic records for the collection with the given collection_id.
+pub fn try_fetch_graphics_details(
+    collection_id: u64,
+    page: &PageRequest,
+) -> Result<Page<IndexedGraphic>, String> {
+    let ogids = try_fetch_graphics_page(collection_id, page)?;
+    Ok(Page {
+        items: try_fetch_graphics_by_ids(&ogids.items)?,
+        next_cursor: ogids.next_cursor,
+        total: ogids.total,
+    })
 }
 
+/// Returns the full records of the graphics with the given OGIDs, in request order.
+/// OGIDs that are not registered are skipped. At most `MAX_PAGE_SIZE` OGIDs may be requested at once.
+pub fn try_fetch_graphics_by_ids(ogids: &[u64]) -> Result<Vec<IndexedGraphic>, String> {
+    if ogids.len() as u64 > MAX_PAGE_SIZE {
+        return Err(format!(
+            "Too many OGIDs requested: {} (maximum is {})",
+            ogids.len(),
+            MAX_PAGE_SIZE
+        ));
+    }
+    Ok(ogids.iter().filter_map(|ogid| find_graphic(*ogid)).collect())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(cursor: Option<u64>, limit: Option<u64>) -> PageRequest {
+        PageRequest { cursor, limit }
+    }
+
+    #[test]
+    fn pages_through_items() {
+        let first = paginate(0..10u64, 10, &page(None, Some(4)));
+        assert_eq!(first.items, [0, 1, 2, 3]);
+        assert_eq!(first.next_cursor, Some(4));
+        assert_eq!(first.total, 10);
+        let last = paginate(0..10u64, 10, &page(Some(8), Some(4)));
+        assert_eq!(last.items, [8, 9]);
+        assert_eq!(last.next_cursor, None);
+    }
+
+    #[test]
+    fn ends_exactly_at_the_last_item() {
+        let last = paginate(0..8u64, 8, &page(Some(4), Some(4)));
+        assert_eq!(last.items, [4, 5, 6, 7]);
+        assert_eq!(last.next_cursor, None);
+        let past_end = paginate(0..8u64, 8, &page(Some(8), None));
+        assert!(past_end.items.is_empty());
+        assert_eq!(past_end.next_cursor, None);
+    }
+
+    #[test]
+    fn saturates_cursors_beyond_usize() {
+        // On wasm32 `usize` is 32 bits, so a cursor of 2^32 or more must not wrap around to the first page.
+        for cursor in [u32::MAX as u64 + 1, u64::MAX] {
+            let result = paginate(0..10u64, 10, &page(Some(cursor), None));
+            assert!(result.items.is_empty());
+            assert_eq!(result.next_cursor, None);
+        }
+    }
+
+    #[test]
+    fn clamps_the_limit() {
+        let result = paginate(0..1000u64, 1000, &page(None, Some(u64::MAX)));
+        assert_eq!(result.items.len() as u64, MAX_PAGE_SIZE);
+        assert_eq!(result.next_cursor, Some(MAX_PAGE_SIZE));
+        let result = paginate(0..1000u64, 1000, &page(None, Some(0)));
+        assert_eq!(result.items, [0]);
+        let result = paginate(0..1000u64, 1000, &page(None, None));
+        assert_eq!(result.items.len() as u64, DEFAULT_PAGE_SIZE);
+    }
+}
@@ -0,0 +1,188 @@
+// File: src/og_backend/src/history.rs
+
+use crate::frontend_api::CollectionInfo;
+use crate::memory::{
+    get_memory, Memory, COLLECTION_HISTORY_MEMORY_ID, GRAPHIC_HISTORY_MEMORY_ID, HISTORY_SEEDED_MEMORY_ID,
+};
+use crate::registry::{IndexedCollection, IndexedGraphic};
+use candid::{CandidType, Decode, Encode};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{StableBTreeMap, StableCell, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use time::macros::format_description;
+use time::PrimitiveDateTime;
+
+/// A version of a graphic, in effect from `recorded_at` until the next version of the same graphic.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct GraphicVersion {
+    /// Nanoseconds since the epoch.
+    pub recorded_at: u64,
+    /// `None` once the graphic has been removed from the registry.
+    pub graphic: Option<IndexedGraphic>,
+}
+
+/// A version of a collection, in effect from `recorded_at` until the next version of the same collection.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CollectionVersion {
+    /// Nanoseconds since the epoch.
+    pub recorded_at: u64,
+    /// `None` once the collection has been unregistered.
+    pub collection: Option<IndexedCollection>,
+}
+
+/// A collection and its graphics as the registry showed them at a past moment.
+#[derive(CandidType, Serialize)]
+pub struct CollectionSnapshot {
+    /// When the shown version of the collection was recorded, in nanoseconds since the epoch.
+    pub recorded_at: u64,
+    pub collection: CollectionInfo,
+    pub graphics: Vec<IndexedGraphic>,
+}
+
+impl Storable for GraphicVersion {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("failed to encode GraphicVersion"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("failed to decode GraphicVersion")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl Storable for CollectionVersion {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("failed to encode CollectionVersion"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("failed to decode CollectionVersion")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// Versions are keyed by (record ID, recorded_at, seq), so the version in effect at a given moment
+// is the last entry at or before it. `seq` orders the versions recorded within the same round,
+// which share a timestamp.
+type VersionKey = (u64, u64, u64);
+
+thread_local! {
+    static COLLECTION_VERSIONS: RefCell<StableBTreeMap<VersionKey, CollectionVersion, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(COLLECTION_HISTORY_MEMORY_ID)));
+    static GRAPHIC_VERSIONS: RefCell<StableBTreeMap<VersionKey, GraphicVersion, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(GRAPHIC_HISTORY_MEMORY_ID)));
+    /// Set once every stored record has a version, so that upgrades no longer seed.
+    static SEEDED: RefCell<StableCell<bool, Memory>> = RefCell::new(
+        StableCell::init(get_memory(HISTORY_SEEDED_MEMORY_ID), false)
+            .expect("failed to initialize the history seeding flag"),
+    );
+}
+
+/// Returns the key for a new version of a record, after any version recorded at the same time.
+fn next_key<V: Storable>(versions: &StableBTreeMap<VersionKey, V, Memory>, id: u64, recorded_at: u64) -> VersionKey {
+    let seq = versions
+        .range((id, recorded_at, 0)..=(id, recorded_at, u64::MAX))
+        .next_back()
+        .map_or(0, |((_, _, seq), _)| seq + 1);
+    (id, recorded_at, seq)
+}
+
+/// Records a new version of a collection (`None` for its unregistration).
+pub fn record_collection(collection_id: u64, recorded_at: u64, collection: Option<&IndexedCollection>) {
+    let version = CollectionVersion { recorded_at, collection: collection.cloned() };
+    COLLECTION_VERSIONS.with(|v| {
+        let mut versions = v.borrow_mut();
+        let key = next_key(&versions, collection_id, recorded_at);
+        versions.insert(key, version)
+    });
+}
+
+/// Records a new version of a graphic (`None` for its removal).
+pub fn record_graphic(ogid: u64, recorded_at: u64, graphic: Option<&IndexedGraphic>) {
+    let version = GraphicVersion { recorded_at, graphic: graphic.cloned() };
+    GRAPHIC_VERSIONS.with(|v| {
+        let mut versions = v.borrow_mut();
+        let key = next_key(&versions, ogid, recorded_at);
+        versions.insert(key, version)
+    });
+}
+
+/// Converts a record timestamp ("YYYY-MM-DD hh:mm:ss", UTC) to nanoseconds since the epoch.
+fn timestamp_nanos(timestamp: &str) -> Option<u64> {
+    let format = format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+    let nanos = PrimitiveDateTime::parse(timestamp, &format).ok()?.assume_utc().unix_timestamp_nanos();
+    u64::try_from(nanos).ok()
+}
+
+/// Returns true once every stored record has been given a version by `seed_records`.
+/// Records registered since then get their versions as they change.
+pub fn is_seeded() -> bool {
+    SEEDED.with(|seeded| *seeded.borrow().get())
+}
+
+/// Records that every stored record has a version, so that later upgrades skip `seed_records`.
+pub fn mark_seeded() {
+    SEEDED.with(|seeded| {
+        seeded
+            .borrow_mut()
+            .set(true)
+            .expect("failed to write the history seeding flag");
+    });
+}
+
+/// Gives records stored before versions were kept a first version, dated from their update
+/// (or registration) timestamp. Records that already have a history are left untouched.
+pub fn seed_records(collection: &IndexedCollection, graphics: &[IndexedGraphic]) {
+    let id = collection.collection_id;
+    if COLLECTION_VERSIONS.with(|v| v.borrow().range((id, 0, 0)..=(id, u64::MAX, u64::MAX)).next().is_none()) {
+        let since = collection.update_timestamp.as_deref().unwrap_or(&collection.registration_timestamp);
+        record_collection(id, timestamp_nanos(since).unwrap_or_default(), Some(collection));
+    }
+    for graphic in graphics {
+        let ogid = graphic.ogid;
+        if GRAPHIC_VERSIONS.with(|v| v.borrow().range((ogid, 0, 0)..=(ogid, u64::MAX, u64::MAX)).next().is_none()) {
+            let since = graphic.update_timestamp.as_deref().unwrap_or(&graphic.registration_timestamp);
+            record_graphic(ogid, timestamp_nanos(since).unwrap_or_default(), Some(graphic));
+        }
+    }
+}
+
+/// Returns every recorded version of a graphic, oldest first.
+pub fn try_fetch_graphic_history(ogid: u64) -> Result<Vec<GraphicVersion>, String> {
+    let versions: Vec<GraphicVersion> = GRAPHIC_VERSIONS.with(|v| {
+        v.borrow()
+            .range((ogid, 0, 0)..=(ogid, u64::MAX, u64::MAX))
+            .map(|(_, version)| version)
+            .collect()
+    });
+    if versions.is_empty() {
+        return Err("Graphic not found".to_string());
+    }
+    Ok(versions)
+}
+
+fn graphic_at(ogid: u64, timestamp: u64) -> Option<IndexedGraphic> {
+    GRAPHIC_VERSIONS
+        .with(|v| v.borrow().range((ogid, 0, 0)..=(ogid, timestamp, u64::MAX)).next_back())
+        .and_then(|(_, version)| version.graphic)
+}
+
+/// Reconstructs a collection and its graphics as they were at `timestamp` (nanoseconds since the epoch).
+pub fn try_fetch_collection_at(collection_id: u64, timestamp: u64) -> Result<CollectionSnapshot, String> {
+    let version = COLLECTION_VERSIONS
+        .with(|v| v.borrow().range((collection_id, 0, 0)..=(collection_id, timestamp, u64::MAX)).next_back())
+        .map(|(_, version)| version);
+    let Some(CollectionVersion { recorded_at, collection: Some(collection) }) = version else {
+        return Err("Collection not registered at that time".to_string());
+    };
+    let graphics = collection.graphics.iter().filter_map(|ogid| graphic_at(*ogid, timestamp)).collect();
+    Ok(CollectionSnapshot {
+        recorded_at,
+        collection: CollectionInfo::from(collection),
+        graphics,
+    })
+}
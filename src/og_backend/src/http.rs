@@ -0,0 +1,246 @@
+// File: src/og_backend/src/http.rs
+
+use crate::certification;
+use crate::frontend_api::{self, CollectionOrder, PageRequest};
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// A request forwarded by the HTTP gateway.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// The response returned to the HTTP gateway.
+#[derive(CandidType, Serialize, Clone, Debug)]
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+fn json_response(status_code: u16, body: Vec<u8>) -> HttpResponse {
+    HttpResponse {
+        status_code,
+        headers: vec![
+            ("Content-Type".to_string(), "application/json".to_string()),
+            ("Access-Control-Allow-Origin".to_string(), "*".to_string()),
+        ],
+        body,
+    }
+}
+
+/// Builds an error response in the same shape as the legacy text endpoints' errors.
+fn error_response(status_code: u16, code: &str, message: impl Into<String>) -> HttpResponse {
+    let message = message.into();
+    json_response(
+        status_code,
+        json!({ "error": { "code": code, "message": message } }).to_string().into_bytes(),
+    )
+}
+
+/// Serializes a successful result.
+/// Bodies are produced by `certification::json_body`, the same serialization the certified tree hashes.
+fn ok_response<T: Serialize>(value: &T) -> HttpResponse {
+    match certification::json_body(value) {
+        Ok(body) => json_response(200, body),
+        Err(e) => error_response(500, "SERIALIZATION_ERROR", e),
+    }
+}
+
+/// Serializes a successful result, or turns a lookup failure into a 404.
+fn ok_or_not_found<T: Serialize>(result: Result<T, String>) -> HttpResponse {
+    match result {
+        Ok(value) => ok_response(&value),
+        Err(e) => error_response(404, "NOT_FOUND", e),
+    }
+}
+
+/// Reads the `cursor` and `limit` query parameters into a page request.
+fn page_request(query: &str) -> Result<PageRequest, String> {
+    let mut page = PageRequest::default();
+    for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+        let parse = || value.parse::<u64>().map_err(|_| format!("Invalid '{}' parameter: '{}'", key, value));
+        match key {
+            "cursor" => page.cursor = Some(parse()?),
+            "limit" => page.limit = Some(parse()?),
+            _ => {}
+        }
+    }
+    Ok(page)
+}
+
+/// Reads the `order` query parameter of the collection list: `registered` (the default), `recent` or `title`.
+fn collection_order(query: &str) -> Result<CollectionOrder, String> {
+    match query.split('&').filter_map(|pair| pair.split_once('=')).find(|(key, _)| *key == "order") {
+        None | Some((_, "registered")) => Ok(CollectionOrder::Registered),
+        Some((_, "recent")) => Ok(CollectionOrder::Recent),
+        Some((_, "title")) => Ok(CollectionOrder::Title),
+        Some((_, value)) => Err(format!("Invalid 'order' parameter: '{}'", value)),
+    }
+}
+
+/// A route of the read-only JSON API.
+#[derive(Debug, PartialEq)]
+enum Route {
+    Collections,
+    Collection(u64),
+    CollectionGraphics(u64),
+    Graphic(u64),
+}
+
+/// Matches a request path against the route table.
+/// Fails with a 400 for a malformed ID and a 404 for an unknown path.
+fn route(path: &str) -> Result<Route, HttpResponse> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let id = |segment: &str| {
+        segment
+            .parse::<u64>()
+            .map_err(|_| error_response(400, "INVALID_ARGUMENT", format!("Invalid ID '{}'", segment)))
+    };
+    match segments.as_slice() {
+        ["api", "collections"] => Ok(Route::Collections),
+        ["api", "collections", collection_id] => id(collection_id).map(Route::Collection),
+        ["api", "collections", collection_id, "graphics"] => id(collection_id).map(Route::CollectionGraphics),
+        ["api", "graphics", ogid] => id(ogid).map(Route::Graphic),
+        _ => Err(error_response(404, "NOT_FOUND", format!("No route for '{}'", path))),
+    }
+}
+
+/// Serves a matched route.
+fn respond(route: Route, query: &str) -> HttpResponse {
+    let page = match page_request(query) {
+        Ok(page) => page,
+        Err(e) => return error_response(400, "INVALID_ARGUMENT", e),
+    };
+    match route {
+        Route::Collections => match collection_order(query) {
+            Ok(order) => ok_response(&frontend_api::collection_ids_page(&page, order)),
+            Err(e) => error_response(400, "INVALID_ARGUMENT", e),
+        },
+        Route::Collection(collection_id) => ok_or_not_found(frontend_api::try_fetch_collection(collection_id)),
+        Route::CollectionGraphics(collection_id) => {
+            ok_or_not_found(frontend_api::try_fetch_graphics_details(collection_id, &page))
+        }
+        Route::Graphic(ogid) => ok_or_not_found(frontend_api::try_fetch_graphic(ogid)),
+    }
+}
+
+/// Attaches the certificate of a successful response, or the skip-certification headers of its path.
+fn attach_certificate(response: &mut HttpResponse, path: &str) {
+    let certified = if response.status_code == 200 {
+        certification::http_certificate_header(path)
+    } else {
+        None
+    };
+    match certified {
+        Some(header) => response.headers.push(header),
+        None => response.headers.extend(certification::http_skip_certification_headers(path)),
+    }
+}
+
+/// Serves the read-only JSON API:
+/// `/api/collections`, `/api/collections/{id}`, `/api/collections/{id}/graphics` and `/api/graphics/{ogid}`.
+/// List routes accept the `cursor` and `limit` query parameters and return one page, as the paged Candid queries do;
+/// the collection list also accepts `order`.
+/// Single-record responses carry an `IC-Certificate` header, so the gateway can verify them against the certified tree.
+/// List pages cannot be certified by path alone; they carry the version 2 skip-certification expression for their
+/// exact path, so the gateway serves them (and errors on those routes) on the certified domain as well.
+/// Other errors, such as a 404 for an unknown record, are not certified and only served through the raw domain.
+/// Every response carries a `Content-Length`; a HEAD request gets the headers of the matching GET and an empty body.
+pub fn handle_request(request: &HttpRequest) -> HttpResponse {
+    let (path, query) = request.url.split_once('?').unwrap_or((&request.url, ""));
+    let mut response = match request.method.as_str() {
+        "GET" | "HEAD" => {
+            let mut response = route(path).map(|route| respond(route, query)).unwrap_or_else(|error| error);
+            attach_certificate(&mut response, path);
+            response
+        }
+        _ => error_response(405, "METHOD_NOT_ALLOWED", "Only GET and HEAD requests are supported"),
+    };
+    response
+        .headers
+        .push(("Content-Length".to_string(), response.body.len().to_string()));
+    if request.method == "HEAD" {
+        response.body.clear();
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, url: &str) -> HttpRequest {
+        HttpRequest {
+            method: method.to_string(),
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    fn route_status(path: &str) -> Result<Route, u16> {
+        route(path).map_err(|response| response.status_code)
+    }
+
+    #[test]
+    fn matches_the_route_table() {
+        assert_eq!(route_status("/api/collections"), Ok(Route::Collections));
+        assert_eq!(route_status("/api/collections/"), Ok(Route::Collections));
+        assert_eq!(route_status("/api/collections/12"), Ok(Route::Collection(12)));
+        assert_eq!(route_status("/api/collections/12/graphics"), Ok(Route::CollectionGraphics(12)));
+        assert_eq!(route_status("/api/graphics/7"), Ok(Route::Graphic(7)));
+    }
+
+    #[test]
+    fn rejects_malformed_ids_and_unknown_paths() {
+        assert_eq!(route_status("/api/collections/abc"), Err(400));
+        assert_eq!(route_status("/api/collections/-1/graphics"), Err(400));
+        assert_eq!(route_status("/api/graphics/18446744073709551616"), Err(400));
+        assert_eq!(route_status("/"), Err(404));
+        assert_eq!(route_status("/api"), Err(404));
+        assert_eq!(route_status("/api/graphics"), Err(404));
+        assert_eq!(route_status("/api/collections/12/graphics/7"), Err(404));
+        assert_eq!(route_status("/api/collections/12/tags"), Err(404));
+    }
+
+    #[test]
+    fn reads_order_and_page_parameters() {
+        assert_eq!(collection_order(""), Ok(CollectionOrder::Registered));
+        assert_eq!(collection_order("limit=5&order=title"), Ok(CollectionOrder::Title));
+        assert_eq!(collection_order("order=recent"), Ok(CollectionOrder::Recent));
+        assert!(collection_order("order=random").is_err());
+        let page = page_request("cursor=10&limit=5&order=title").unwrap();
+        assert_eq!((page.cursor, page.limit), (Some(10), Some(5)));
+        assert!(page_request("cursor=x").is_err());
+    }
+
+    #[test]
+    fn answers_head_with_the_headers_of_get_and_no_body() {
+        for url in ["/api/graphics/abc", "/missing"] {
+            let get = handle_request(&request("GET", url));
+            let head = handle_request(&request("HEAD", url));
+            assert!(!get.body.is_empty());
+            assert!(head.body.is_empty());
+            assert_eq!(head.status_code, get.status_code);
+            assert_eq!(head.headers, get.headers);
+            assert!(get
+                .headers
+                .contains(&("Content-Length".to_string(), get.body.len().to_string())));
+        }
+    }
+
+    #[test]
+    fn rejects_other_methods() {
+        let response = handle_request(&request("POST", "/api/collections"));
+        assert_eq!(response.status_code, 405);
+        assert!(response
+            .headers
+            .contains(&("Content-Length".to_string(), response.body.len().to_string())));
+    }
+}
@@ -0,0 +1,334 @@
+// File: src/og_backend/src/icrc3.rs
+
+use crate::certification;
+use crate::memory::{get_memory, Memory, BLOCKS_DATA_MEMORY_ID, BLOCKS_INDEX_MEMORY_ID};
+use crate::registry::{IndexedCollection, IndexedGraphic};
+use candid::{CandidType, Decode, Encode, Int, Nat, Principal};
+use ic_cdk::api::time;
+use ic_certified_map::Hash;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{StableLog, Storable};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+// Block types of the registry's mutation log. Each block is a map with `btype`, `ts` (nanoseconds since the epoch),
+// `phash` (hash of the previous block, absent on the first) and `tx`. Every `tx` holds the `collection_id` and the
+// `caller` that triggered the operation; registrations and updates add the `canister_id`, the `metadata_path` and,
+// under `graphics`, the `ogid`, `asset` and pinned `content_hash` of every graphic they added or changed.
+// Updates list the OGIDs they removed under `removed`.
+const REGISTER_BLOCK_TYPE: &str = "og_register_collection";
+const UPDATE_BLOCK_TYPE: &str = "og_update_collection";
+const UNREGISTER_BLOCK_TYPE: &str = "og_unregister_collection";
+const BLOCK_TYPES_URL: &str = "https://github.com/forria64/onchain_graphics/blob/main/src/og_backend/src/icrc3.rs";
+
+/// Maximum number of blocks returned by one `icrc3_get_blocks` call, across all requested ranges.
+const MAX_BLOCKS_PER_REQUEST: u64 = 100;
+
+/// The ICRC-3 generic value, in which blocks are expressed.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum Value {
+    Blob(Vec<u8>),
+    Text(String),
+    Nat(Nat),
+    Int(Int),
+    Array(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+impl Storable for Value {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("failed to encode block"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("failed to decode block")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetBlocksRequest {
+    pub start: Nat,
+    pub length: Nat,
+}
+
+#[derive(CandidType, Serialize, Clone, Debug)]
+pub struct BlockWithId {
+    pub id: Nat,
+    pub block: Value,
+}
+
+candid::define_function!(pub GetBlocksCallback : (Vec<GetBlocksRequest>) -> (GetBlocksResult) query);
+
+#[derive(CandidType, Clone, Debug)]
+pub struct ArchivedBlocks {
+    pub args: Vec<GetBlocksRequest>,
+    pub callback: GetBlocksCallback,
+}
+
+/// The reply of `icrc3_get_blocks`. The registry keeps its whole log, so `archived_blocks` is always empty.
+#[derive(CandidType, Clone, Debug)]
+pub struct GetBlocksResult {
+    pub log_length: Nat,
+    pub blocks: Vec<BlockWithId>,
+    pub archived_blocks: Vec<ArchivedBlocks>,
+}
+
+#[derive(CandidType, Serialize, Clone, Debug)]
+pub struct DataCertificate {
+    pub certificate: Vec<u8>,
+    /// CBOR-encoded hash tree revealing `last_block_index` and `last_block_hash`.
+    pub hash_tree: Vec<u8>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetArchivesArgs {
+    pub from: Option<Principal>,
+}
+
+#[derive(CandidType, Serialize, Clone, Debug)]
+pub struct ArchiveInfo {
+    pub canister_id: Principal,
+    pub start: Nat,
+    pub end: Nat,
+}
+
+#[derive(CandidType, Serialize, Clone, Debug)]
+pub struct SupportedBlockType {
+    pub block_type: String,
+    pub url: String,
+}
+
+thread_local! {
+    static BLOCKS: RefCell<StableLog<Value, Memory, Memory>> = RefCell::new(
+        StableLog::init(get_memory(BLOCKS_INDEX_MEMORY_ID), get_memory(BLOCKS_DATA_MEMORY_ID))
+            .expect("failed to initialize the block log"),
+    );
+}
+
+fn sha256(bytes: &[u8]) -> Hash {
+    Sha256::digest(bytes).into()
+}
+
+/// Computes the representation-independent hash of a value, as specified by ICRC-3.
+fn hash_value(value: &Value) -> Hash {
+    match value {
+        Value::Blob(bytes) => sha256(bytes),
+        Value::Text(text) => sha256(text.as_bytes()),
+        Value::Nat(nat) => {
+            let mut leb128 = Vec::new();
+            nat.encode(&mut leb128).expect("failed to encode Nat");
+            sha256(&leb128)
+        }
+        Value::Int(int) => {
+            let mut sleb128 = Vec::new();
+            int.encode(&mut sleb128).expect("failed to encode Int");
+            sha256(&sleb128)
+        }
+        Value::Array(values) => sha256(&values.iter().flat_map(hash_value).collect::<Vec<u8>>()),
+        Value::Map(entries) => {
+            let mut pairs: Vec<[u8; 64]> = entries
+                .iter()
+                .map(|(key, value)| {
+                    let mut pair = [0u8; 64];
+                    pair[..32].copy_from_slice(&sha256(key.as_bytes()));
+                    pair[32..].copy_from_slice(&hash_value(value));
+                    pair
+                })
+                .collect();
+            pairs.sort_unstable();
+            sha256(pairs.concat().as_slice())
+        }
+    }
+}
+
+fn nat(value: u64) -> Value {
+    Value::Nat(Nat::from(value))
+}
+
+fn text(value: &str) -> Value {
+    Value::Text(value.to_string())
+}
+
+fn map(entries: Vec<(&str, Value)>) -> Value {
+    Value::Map(entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+}
+
+/// Describes a graphic's provenance: its OGID, asset path and, once pinned, the SHA-256 of its content.
+fn graphic_entry(graphic: &IndexedGraphic) -> Value {
+    let mut entry = vec![("ogid", nat(graphic.ogid)), ("asset", text(&graphic.asset))];
+    if let Some(hash) = graphic.content_hash.as_ref().and_then(|hash| hex::decode(hash).ok()) {
+        entry.push(("content_hash", Value::Blob(hash)));
+    }
+    map(entry)
+}
+
+/// The fields shared by the registration and update blocks of a collection.
+fn collection_tx(caller: Principal, collection: &IndexedCollection) -> Vec<(&'static str, Value)> {
+    let mut tx = vec![
+        ("collection_id", nat(collection.collection_id)),
+        ("caller", Value::Blob(caller.as_slice().to_vec())),
+        ("metadata_path", text(collection.metadata_path())),
+    ];
+    if let Some(canister_id) = collection.canister_id {
+        tx.push(("canister_id", Value::Blob(canister_id.as_slice().to_vec())));
+    }
+    tx
+}
+
+/// Returns the index and hash of the last block, if any.
+fn last_block() -> Option<(u64, Hash)> {
+    BLOCKS.with(|blocks| {
+        let blocks = blocks.borrow();
+        let index = blocks.len().checked_sub(1)?;
+        blocks.get(index).map(|block| (index, hash_value(&block)))
+    })
+}
+
+/// Appends a block chained to the previous one and certifies the new tip.
+fn append(block_type: &str, tx: Vec<(&str, Value)>) {
+    let previous = last_block();
+    let mut block = vec![("btype", text(block_type)), ("ts", nat(time())), ("tx", map(tx))];
+    if let Some((_, previous_hash)) = previous {
+        block.push(("phash", Value::Blob(previous_hash.to_vec())));
+    }
+    let block = map(block);
+    let hash = hash_value(&block);
+    let index = BLOCKS.with(|blocks| blocks.borrow().append(&block).expect("failed to append to the block log"));
+    certification::certify_tip(index, hash);
+}
+
+/// Records the registration of a collection and all its graphics.
+pub fn record_registration(caller: Principal, collection: &IndexedCollection, graphics: &[IndexedGraphic]) {
+    let mut tx = collection_tx(caller, collection);
+    tx.push(("graphics", Value::Array(graphics.iter().map(graphic_entry).collect())));
+    append(REGISTER_BLOCK_TYPE, tx);
+}
+
+/// Records an update of a collection, with the graphics it added or changed and the OGIDs it removed.
+pub fn record_update(
+    caller: Principal,
+    collection: &IndexedCollection,
+    changed: &[IndexedGraphic],
+    removed: &[u64],
+) {
+    let mut tx = collection_tx(caller, collection);
+    tx.push(("graphics", Value::Array(changed.iter().map(graphic_entry).collect())));
+    tx.push(("removed", Value::Array(removed.iter().map(|ogid| nat(*ogid)).collect())));
+    append(UPDATE_BLOCK_TYPE, tx);
+}
+
+/// Records the unregistration of a collection.
+pub fn record_unregistration(caller: Principal, collection_id: u64) {
+    append(
+        UNREGISTER_BLOCK_TYPE,
+        vec![
+            ("collection_id", nat(collection_id)),
+            ("caller", Value::Blob(caller.as_slice().to_vec())),
+        ],
+    );
+}
+
+/// Re-certifies the tip of the block log, after the certified tree has been rebuilt.
+pub fn certify_tip() {
+    if let Some((index, hash)) = last_block() {
+        certification::certify_tip(index, hash);
+    }
+}
+
+/// Returns the requested ranges of blocks, clamped to the log and to `MAX_BLOCKS_PER_REQUEST` blocks in total.
+pub fn get_blocks(requests: &[GetBlocksRequest]) -> GetBlocksResult {
+    BLOCKS.with(|blocks| {
+        let blocks = blocks.borrow();
+        let log_length = blocks.len();
+        let mut budget = MAX_BLOCKS_PER_REQUEST;
+        let mut result = Vec::new();
+        for request in requests {
+            let start = u64::try_from(&request.start.0).unwrap_or(u64::MAX).min(log_length);
+            let length = u64::try_from(&request.length.0).unwrap_or(u64::MAX).min(budget);
+            let end = start.saturating_add(length).min(log_length);
+            result.extend(
+                (start..end).filter_map(|id| blocks.get(id).map(|block| BlockWithId { id: Nat::from(id), block })),
+            );
+            budget -= end - start;
+        }
+        GetBlocksResult {
+            log_length: Nat::from(log_length),
+            blocks: result,
+            archived_blocks: Vec::new(),
+        }
+    })
+}
+
+/// Returns the certificate for the tip of the block log, or `None` before the first block.
+pub fn tip_certificate() -> Option<DataCertificate> {
+    certification::tip_certificate().map(|(certificate, hash_tree)| DataCertificate { certificate, hash_tree })
+}
+
+/// Lists the block types the log contains.
+pub fn supported_block_types() -> Vec<SupportedBlockType> {
+    [REGISTER_BLOCK_TYPE, UPDATE_BLOCK_TYPE, UNREGISTER_BLOCK_TYPE]
+        .into_iter()
+        .map(|block_type| SupportedBlockType {
+            block_type: block_type.to_string(),
+            url: BLOCK_TYPES_URL.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Examples from the representation-independent hashing section of the ICRC-3 specification.
+    fn hash_hex(value: &Value) -> String {
+        hex::encode(hash_value(value))
+    }
+
+    #[test]
+    fn hashes_scalars() {
+        assert_eq!(
+            hash_hex(&nat(42)),
+            "684888c0ebb17f374298b65ee2807526c066094c701bcc7ebbe1c1095f494fc1"
+        );
+        assert_eq!(
+            hash_hex(&text("Hello, World!")),
+            "dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f"
+        );
+        assert_eq!(
+            hash_hex(&Value::Blob(vec![0x01, 0x02, 0x03, 0x04])),
+            "9f64a747e1b97f131fabb6b447296c9b6f0201e79fb3c5356e6c77e89b6a806a"
+        );
+        assert_eq!(
+            hash_hex(&Value::Int(Int::from(-42))),
+            "de5a6f78116eca62d7fc5ce159d23ae6b889b365a1739ad2cf36f925a140d0cc"
+        );
+    }
+
+    #[test]
+    fn hashes_arrays() {
+        let value = Value::Array(vec![nat(3), text("foo"), Value::Blob(vec![0x05, 0x06])]);
+        assert_eq!(
+            hash_hex(&value),
+            "514a04011caa503990d446b7dec5d79e19c221ae607fb08b2848c67734d468d6"
+        );
+    }
+
+    #[test]
+    fn hashes_maps_independently_of_entry_order() {
+        let blob = |hex_value: &str| Value::Blob(hex::decode(hex_value).unwrap());
+        let entries = vec![
+            ("from", blob("00abcdef0012340056789a00bcdef000012345678900abcdef01")),
+            ("to", blob("00ab0def0012340056789a00bcdef000012345678900abcdef01")),
+            ("amount", nat(42)),
+            ("created_at", nat(1699218263)),
+            ("memo", nat(0)),
+        ];
+        let expected = "c56ece650e1de4269c5bdeff7875949e3e2033f85b2d193c2ff4f7f78bdcfc75";
+        assert_eq!(hash_hex(&map(entries.clone())), expected);
+        assert_eq!(hash_hex(&map(entries.into_iter().rev().collect())), expected);
+    }
+}
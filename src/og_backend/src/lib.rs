@@ -1,68 +1,104 @@
 // File: src/og_backend/src/lib.rs
 
+mod assets;
+mod attributes;
 mod auth;
+mod certification;
+mod changes;
+mod diff;
+mod error;
 mod frontend_api;
+mod history;
+mod http;
+mod icrc3;
+mod media;
+mod memory;
 mod registry; // Now includes both auth and registry functions
+mod search;
+mod sync;
+mod tags;
+mod validation;
 
+use attributes::AttributeSummary;
+use auth::{Role, RoleAssignment};
 use candid::Principal;
+use certification::Certified;
+use changes::ChangeFeedPage;
+use diff::CollectionDiff;
+use error::OgError;
+use frontend_api::{CollectionInfo, CollectionOrder, Page, PageRequest};
+use history::{CollectionSnapshot, GraphicVersion};
+use http::{HttpRequest, HttpResponse};
+use icrc3::{ArchiveInfo, DataCertificate, GetArchivesArgs, GetBlocksRequest, GetBlocksResult, SupportedBlockType};
+use media::GraphicVerification;
+use registry::IndexedGraphic;
+use search::{SearchFilters, SearchHit};
+use tags::{TagCount, TaggedRecord};
+use validation::ValidationReport;
 use ic_cdk::storage;
-use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
+use ic_cdk_macros::{init, post_upgrade, query, update};
 use serde_json::json;
 
 #[init]
 fn init() {
     registry::init_state();
+    auth::start_controller_refresh();
+    sync::start_sync_timer();
 }
 
-#[pre_upgrade]
-fn pre_upgrade() {
-    let state = registry::get_state();
-    storage::stable_save((state,)).expect("failed to save state");
-}
-
+/// Registry data lives in stable-memory maps and survives upgrades without serialization.
+/// Canisters upgraded from a release that still used `stable_save` are migrated once here.
 #[post_upgrade]
 fn post_upgrade() {
-    let (state,): (registry::RegistryState,) =
-        storage::stable_restore().expect("failed to restore state");
-    registry::set_state(state);
+    if memory::has_legacy_layout() {
+        let (state,): (registry::RegistryState,) =
+            storage::stable_restore().expect("failed to restore legacy state");
+        registry::set_state(state);
+    } else {
+        registry::rebuild_index();
+    }
+    auth::start_controller_refresh();
+    sync::start_sync_timer();
 }
 
-/// Controller-only update call to register a new collection.
+/// Curator-only update call to register a new collection.
+/// The metadata is read from `metadata_path` within the asset canister (default "/og_metadata.json"),
+/// so a single asset canister can host several collections.
 #[update]
-async fn register_collection(canister_id: Principal) -> String {
+async fn register_collection(canister_id: Principal, metadata_path: Option<String>) -> String {
     let caller_id = ic_cdk::api::caller();
-    if let Err(err) = auth::authenticate_caller(caller_id).await {
+    if let Err(err) = auth::authorize(caller_id, Role::Curator) {
         return json!({ "error": { "code": "UNAUTHORIZED", "message": err } }).to_string();
     }
-    match registry::try_register_collection(canister_id).await {
+    match registry::try_register_collection(canister_id, metadata_path, caller_id).await {
         Ok(_) => json!({ "ok": "Collection registered successfully." }).to_string(),
         Err(err_msg) => json!({ "error": { "code": "REGISTER_COLLECTION_FAILED", "message": err_msg } }).to_string(),
     }
 }
 
-/// Controller-only update call to update an existing collection.
-/// This API first checks the caller's principal via the auth function,
+/// Curator-only update call to update an existing collection.
+/// This API first checks the caller's role via the auth function,
 /// then retains the original registration timestamp and creates a new update timestamp.
 #[update]
 async fn update_collection(collection_id: u64, canister_id: Principal) -> String {
     let caller_id = ic_cdk::api::caller();
-    if let Err(err) = auth::authenticate_caller(caller_id).await {
+    if let Err(err) = auth::authorize(caller_id, Role::Curator) {
         return json!({ "error": { "code": "UNAUTHORIZED", "message": err } }).to_string();
     }
-    match registry::try_update_collection(collection_id, canister_id).await {
+    match registry::try_update_collection(collection_id, canister_id, caller_id).await {
         Ok(_) => json!({ "ok": "Collection updated successfully." }).to_string(),
         Err(err_msg) => json!({ "error": { "code": "UPDATE_COLLECTION_FAILED", "message": err_msg } }).to_string(),
     }
 }
 
-/// Controller-only update call to unregister an existing collection.
+/// Curator-only update call to unregister an existing collection.
 #[update]
-async fn unregister_collection(collection_id: u64) -> String {
+fn unregister_collection(collection_id: u64) -> String {
     let caller_id = ic_cdk::api::caller();
-    if let Err(err) = auth::authenticate_caller(caller_id).await {
+    if let Err(err) = auth::authorize(caller_id, Role::Curator) {
         return json!({ "error": { "code": "UNAUTHORIZED", "message": err } }).to_string();
     }
-    match registry::try_unregister_collection(collection_id) {
+    match registry::try_unregister_collection(collection_id, caller_id) {
         Ok(_) => json!({ "ok": "Collection unregistered successfully." }).to_string(),
         Err(err_msg) => json!({ "error": { "code": "UNREGISTER_COLLECTION_FAILED", "message": err_msg } }).to_string(),
     }
@@ -71,12 +107,9 @@ async fn unregister_collection(collection_id: u64) -> String {
 /// PUBLIC API CALL: Returns all registered collection IDs.
 #[query]
 fn fetch_collections() -> String {
-    match frontend_api::try_fetch_collections() {
-        Ok(ids) => serde_json::to_string_pretty(&ids).unwrap_or_else(|e| {
-            json!({ "error": { "code": "SERIALIZATION_ERROR", "message": e.to_string() } }).to_string()
-        }),
-        Err(e) => json!({ "error": { "code": "FETCH_COLLECTIONS_FAILED", "message": e } }).to_string(),
-    }
+    serde_json::to_string_pretty(&frontend_api::collection_ids()).unwrap_or_else(|e| {
+        json!({ "error": { "code": "SERIALIZATION_ERROR", "message": e.to_string() } }).to_string()
+    })
 }
 
 /// PUBLIC API CALL: Returns collection details (excluding the graphics vector).
@@ -112,3 +145,286 @@ fn fetch_graphic(ogid: u64) -> String {
     }
 }
 
+
+/// Curator-only update call (v2) to register a new collection.
+/// The metadata is read from `metadata_path` (default "/og_metadata.json").
+/// Returns the collection ID assigned to the new collection.
+#[update]
+async fn register_collection_v2(
+    canister_id: Principal,
+    metadata_path: Option<String>,
+) -> Result<u64, OgError> {
+    let caller_id = ic_cdk::api::caller();
+    auth::authorize(caller_id, Role::Curator)
+        .map_err(|reason| OgError::Unauthorized { reason })?;
+    registry::try_register_collection(canister_id, metadata_path, caller_id)
+        .await
+        .map_err(|reason| OgError::RegisterFailed { reason })
+}
+
+/// Curator-only update call (v2) to update an existing collection.
+#[update]
+async fn update_collection_v2(collection_id: u64, canister_id: Principal) -> Result<(), OgError> {
+    let caller_id = ic_cdk::api::caller();
+    auth::authorize(caller_id, Role::Curator)
+        .map_err(|reason| OgError::Unauthorized { reason })?;
+    registry::try_update_collection(collection_id, canister_id, caller_id)
+        .await
+        .map_err(|reason| OgError::UpdateFailed { reason })
+}
+
+/// Curator-only update call to enable, change or (with no interval) disable the automatic resync of a collection.
+/// Due collections are updated from their asset canister every `interval_seconds`; repeated failures back off.
+#[update]
+fn set_sync_interval(collection_id: u64, interval_seconds: Option<u64>) -> Result<(), OgError> {
+    auth::authorize(ic_cdk::api::caller(), Role::Curator)
+        .map_err(|reason| OgError::Unauthorized { reason })?;
+    sync::try_set_sync_interval(collection_id, interval_seconds)
+        .map_err(|reason| OgError::InvalidArgument { reason })
+}
+
+/// Curator-only update call (v2) to unregister an existing collection.
+#[update]
+fn unregister_collection_v2(collection_id: u64) -> Result<(), OgError> {
+    let caller_id = ic_cdk::api::caller();
+    auth::authorize(caller_id, Role::Curator)
+        .map_err(|reason| OgError::Unauthorized { reason })?;
+    registry::try_unregister_collection(collection_id, caller_id)
+        .map_err(|reason| OgError::UnregisterFailed { reason })
+}
+
+/// PUBLIC API CALL (v2): Returns all registered collection IDs.
+#[query]
+fn fetch_collections_v2() -> Result<Vec<u64>, OgError> {
+    Ok(frontend_api::collection_ids())
+}
+
+/// PUBLIC API CALL (v2): Returns one page of registered collection IDs, oldest registration first
+/// unless another order is given.
+#[query]
+fn fetch_collections_page(page: PageRequest, order: Option<CollectionOrder>) -> Result<Page<u64>, OgError> {
+    Ok(frontend_api::collection_ids_page(&page, order.unwrap_or_default()))
+}
+
+/// PUBLIC API CALL (v2): Returns collection details (excluding the graphics vector).
+#[query]
+fn fetch_collection_v2(collection_id: u64) -> Result<CollectionInfo, OgError> {
+    frontend_api::try_fetch_collection(collection_id).map_err(|reason| OgError::NotFound { reason })
+}
+
+/// PUBLIC API CALL (v2): Returns all graphic OGIDs for the given collection.
+#[query]
+fn fetch_graphics_v2(collection_id: u64) -> Result<Vec<u64>, OgError> {
+    frontend_api::try_fetch_graphics(collection_id).map_err(|reason| OgError::NotFound { reason })
+}
+
+/// PUBLIC API CALL (v2): Returns one page of graphic OGIDs for the given collection.
+#[query]
+fn fetch_graphics_page(collection_id: u64, page: PageRequest) -> Result<Page<u64>, OgError> {
+    frontend_api::try_fetch_graphics_page(collection_id, &page)
+        .map_err(|reason| OgError::NotFound { reason })
+}
+
+/// PUBLIC API CALL (v2): Returns the full details of a registered graphic given its OGID.
+#[query]
+fn fetch_graphic_v2(ogid: u64) -> Result<IndexedGraphic, OgError> {
+    frontend_api::try_fetch_graphic(ogid).map_err(|reason| OgError::NotFound { reason })
+}
+
+/// PUBLIC API CALL (v2): Returns one page of full graphic records for the given collection.
+#[query]
+fn fetch_graphics_details(
+    collection_id: u64,
+    page: PageRequest,
+) -> Result<Page<IndexedGraphic>, OgError> {
+    frontend_api::try_fetch_graphics_details(collection_id, &page)
+        .map_err(|reason| OgError::NotFound { reason })
+}
+
+/// PUBLIC API CALL (v2): Returns the details of a collection together with a certificate and witness,
+/// proving that they are part of the registry's certified state.
+#[query]
+fn fetch_collection_certified(collection_id: u64) -> Result<Certified<CollectionInfo>, OgError> {
+    let collection = frontend_api::try_fetch_collection(collection_id)
+        .map_err(|reason| OgError::NotFound { reason })?;
+    certification::certify_collection(collection).map_err(|reason| OgError::Internal { reason })
+}
+
+/// PUBLIC API CALL (v2): Returns a graphic together with a certificate and witness,
+/// proving that it is part of the registry's certified state.
+#[query]
+fn fetch_graphic_certified(ogid: u64) -> Result<Certified<IndexedGraphic>, OgError> {
+    let graphic = frontend_api::try_fetch_graphic(ogid).map_err(|reason| OgError::NotFound { reason })?;
+    certification::certify_graphic(graphic).map_err(|reason| OgError::Internal { reason })
+}
+
+/// PUBLIC API CALL (v2): Returns the full details of every registered graphic among the given OGIDs.
+#[query]
+fn fetch_graphics_by_ids(ogids: Vec<u64>) -> Result<Vec<IndexedGraphic>, OgError> {
+    frontend_api::try_fetch_graphics_by_ids(&ogids)
+        .map_err(|reason| OgError::InvalidArgument { reason })
+}
+
+/// PUBLIC API CALL (v2): Lists the distinct attribute keys within a collection, each with its
+/// distinct values and the number of graphics carrying them.
+#[query]
+fn fetch_attributes(collection_id: u64) -> Result<Vec<AttributeSummary>, OgError> {
+    attributes::try_fetch_attributes(collection_id).map_err(|reason| OgError::NotFound { reason })
+}
+
+/// PUBLIC API CALL (v2): Lists every tag in use, with the number of collections and graphics carrying it.
+#[query]
+fn fetch_tags() -> Result<Vec<TagCount>, OgError> {
+    Ok(tags::tag_counts())
+}
+
+/// PUBLIC API CALL (v2): Returns one page of the collections and graphics carrying the given tag.
+/// Tags are matched case-insensitively.
+#[query]
+fn fetch_by_tag(tag: String, page: PageRequest) -> Result<Page<TaggedRecord>, OgError> {
+    tags::try_fetch_by_tag(&tag, &page).map_err(|reason| OgError::InvalidArgument { reason })
+}
+
+/// PUBLIC API CALL (v2): Returns the registry's change feed starting at sequence number `seq`, oldest first.
+/// Indexers resume by passing the returned `next_seq` to the next call.
+#[query]
+fn changes_since(seq: u64, limit: Option<u64>) -> Result<ChangeFeedPage, OgError> {
+    changes::try_changes_since(seq, limit).map_err(|reason| OgError::InvalidArgument { reason })
+}
+
+/// PUBLIC API CALL (v2): Returns every recorded version of a graphic, oldest first, including its removal if
+/// it has been removed from the registry.
+#[query]
+fn fetch_graphic_history(ogid: u64) -> Result<Vec<GraphicVersion>, OgError> {
+    history::try_fetch_graphic_history(ogid).map_err(|reason| OgError::NotFound { reason })
+}
+
+/// PUBLIC API CALL (v2): Returns a collection and its graphics as the registry showed them at `timestamp`
+/// (nanoseconds since the epoch).
+#[query]
+fn fetch_collection_at(collection_id: u64, timestamp: u64) -> Result<CollectionSnapshot, OgError> {
+    history::try_fetch_collection_at(collection_id, timestamp).map_err(|reason| OgError::NotFound { reason })
+}
+
+/// PUBLIC API CALL (ICRC-3): Returns the requested ranges of the hash-chained log of registrations,
+/// updates and unregistrations.
+#[query]
+fn icrc3_get_blocks(requests: Vec<GetBlocksRequest>) -> GetBlocksResult {
+    icrc3::get_blocks(&requests)
+}
+
+/// PUBLIC API CALL (ICRC-3): Returns the certificate over the index and hash of the last block.
+#[query]
+fn icrc3_get_tip_certificate() -> Option<DataCertificate> {
+    icrc3::tip_certificate()
+}
+
+/// PUBLIC API CALL (ICRC-3): Lists the block types of the log.
+#[query]
+fn icrc3_supported_block_types() -> Vec<SupportedBlockType> {
+    icrc3::supported_block_types()
+}
+
+/// PUBLIC API CALL (ICRC-3): Lists archive canisters. The registry keeps its whole log, so there are none.
+#[query]
+fn icrc3_get_archives(_args: GetArchivesArgs) -> Vec<ArchiveInfo> {
+    Vec::new()
+}
+
+/// PUBLIC API CALL (v2): Full-text search over the titles, descriptions and artists of
+/// registered collections and graphics. Returns one page of ranked hits.
+#[query]
+fn search(
+    query: String,
+    filters: SearchFilters,
+    page: PageRequest,
+) -> Result<Page<SearchHit>, OgError> {
+    search::try_search(&query, &filters, &page).map_err(|reason| OgError::InvalidArgument { reason })
+}
+
+/// Viewer-only update call to validate an asset canister's metadata without registering it.
+/// Returns every problem found, each located by its JSON path.
+#[update]
+async fn validate_collection(
+    canister_id: Principal,
+    metadata_path: Option<String>,
+) -> Result<ValidationReport, OgError> {
+    auth::authorize(ic_cdk::api::caller(), Role::Viewer)
+        .map_err(|reason| OgError::Unauthorized { reason })?;
+    Ok(validation::try_validate_collection(canister_id, metadata_path).await)
+}
+
+/// Viewer-only update call to preview how `update_collection` would change a collection.
+/// Returns the changed collection fields and the graphics added, removed and modified, without applying them.
+#[update]
+async fn preview_update(collection_id: u64, canister_id: Principal) -> Result<CollectionDiff, OgError> {
+    auth::authorize(ic_cdk::api::caller(), Role::Viewer)
+        .map_err(|reason| OgError::Unauthorized { reason })?;
+    diff::try_preview_update(collection_id, canister_id).await
+}
+
+/// Viewer-only update call to re-fetch a graphic's asset and check it against the SHA-256 pinned at registration.
+/// Reports `drifted = opt true` if the asset canister now serves different content under the graphic's asset path,
+/// and `drifted = null` if the graphic has no pinned hash to check against.
+#[update]
+async fn verify_graphic(ogid: u64) -> Result<GraphicVerification, OgError> {
+    auth::authorize(ic_cdk::api::caller(), Role::Viewer)
+        .map_err(|reason| OgError::Unauthorized { reason })?;
+    media::try_verify_graphic(ogid).await
+}
+
+/// Admin-only update call to grant a role to a principal, replacing any role it held before.
+/// Controllers implicitly hold every role.
+#[update]
+fn grant_role(principal: Principal, role: Role) -> Result<(), OgError> {
+    auth::authorize(ic_cdk::api::caller(), Role::Admin)
+        .map_err(|reason| OgError::Unauthorized { reason })?;
+    auth::grant_role(principal, role).map_err(|reason| OgError::InvalidArgument { reason })
+}
+
+/// Admin-only update call to revoke the role held by a principal.
+#[update]
+fn revoke_role(principal: Principal) -> Result<(), OgError> {
+    auth::authorize(ic_cdk::api::caller(), Role::Admin)
+        .map_err(|reason| OgError::Unauthorized { reason })?;
+    auth::revoke_role(principal).map_err(|reason| OgError::NotFound { reason })
+}
+
+/// Admin-only query returning every stored role assignment.
+#[query]
+fn fetch_roles() -> Result<Vec<RoleAssignment>, OgError> {
+    auth::authorize(ic_cdk::api::caller(), Role::Admin)
+        .map_err(|reason| OgError::Unauthorized { reason })?;
+    Ok(auth::list_roles())
+}
+
+/// Admin-only query returning the controller list as of the last successful refresh.
+/// Fails with `Internal`, giving the reason of the last failure, if no refresh has succeeded yet
+/// (e.g. because the canister is not one of its own controllers).
+#[query]
+fn fetch_controllers() -> Result<Vec<Principal>, OgError> {
+    auth::authorize(ic_cdk::api::caller(), Role::Admin)
+        .map_err(|reason| OgError::Unauthorized { reason })?;
+    auth::cached_controllers().map_err(|reason| OgError::Internal { reason })
+}
+
+/// Admin-only update call to refresh the cached controller list from the management canister.
+/// Returns the refreshed list. Requires the canister to be one of its own controllers.
+#[update]
+async fn refresh_controllers() -> Result<Vec<Principal>, OgError> {
+    auth::authorize(ic_cdk::api::caller(), Role::Admin)
+        .map_err(|reason| OgError::Unauthorized { reason })?;
+    auth::refresh_controllers()
+        .await
+        .map_err(|reason| OgError::UpdateFailed { reason })
+}
+
+/// PUBLIC HTTP API: Serves read-only JSON over the HTTP gateway, so browsers and tools such as curl
+/// can read the registry without an agent library. See `http::handle_request` for the routes.
+#[query]
+fn http_request(request: HttpRequest) -> HttpResponse {
+    http::handle_request(&request)
+}
+
+// Exposes the Candid interface of this canister (used to regenerate og_backend.did).
+ic_cdk::export_candid!();
@@ -0,0 +1,455 @@
+// File: src/og_backend/src/media.rs
+
+use crate::assets;
+use crate::error::OgError;
+use crate::registry::{find_graphic, GraphicProposal};
+use candid::{CandidType, Principal};
+use futures::future::join_all;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Number of graphic assets fetched concurrently while inspecting a collection.
+const INSPECTION_BATCH_SIZE: usize = 16;
+
+/// The outcome of re-fetching a registered graphic's asset and comparing it with its pinned content hash.
+#[derive(CandidType, Serialize, Clone, Debug)]
+pub struct GraphicVerification {
+    pub ogid: u64,
+    /// Hash recorded at registration; `None` for graphics registered before hashes were pinned.
+    pub expected_hash: Option<String>,
+    pub actual_hash: String,
+    pub byte_size: u64,
+    /// Whether the asset no longer matches the recorded hash; `None` when no hash was pinned,
+    /// so the graphic cannot be verified.
+    pub drifted: Option<bool>,
+}
+
+/// Returns the hex-encoded SHA-256 of an asset's bytes.
+pub fn content_hash(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
+
+/// Identifies the media type of an asset from its leading bytes, if it is a recognized image format.
+pub fn sniff_media_type(content: &[u8]) -> Option<&'static str> {
+    if content.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if content.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if content.starts_with(b"GIF87a") || content.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if content.len() >= 12 && &content[0..4] == b"RIFF" && &content[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if content.len() >= 12 && &content[4..8] == b"ftyp" && matches!(&content[8..12], b"avif" | b"avis") {
+        Some("image/avif")
+    } else if is_svg(content) {
+        Some("image/svg+xml")
+    } else {
+        None
+    }
+}
+
+/// SVG has no magic bytes: look for an `<svg` root element near the start of a text document.
+fn is_svg(content: &[u8]) -> bool {
+    let head = &content[..content.len().min(1024)];
+    let Ok(text) = std::str::from_utf8(head).or_else(|e| std::str::from_utf8(&head[..e.valid_up_to()])) else {
+        return false;
+    };
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    (text.starts_with("<?xml") || text.starts_with("<!--") || text.starts_with("<svg") || text.starts_with("<!DOCTYPE svg"))
+        && text.contains("<svg")
+}
+
+/// Reads the pixel dimensions of an image of the given (sniffed) media type from its header.
+/// Returns `None` for formats without a parsable header or for truncated/corrupt headers.
+pub fn image_dimensions(media_type: &str, content: &[u8]) -> Option<(u32, u32)> {
+    match media_type {
+        "image/png" => png_dimensions(content),
+        "image/gif" => gif_dimensions(content),
+        "image/webp" => webp_dimensions(content),
+        "image/jpeg" => jpeg_dimensions(content),
+        "image/svg+xml" => svg_dimensions(content),
+        _ => None,
+    }
+}
+
+fn be_u16(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32)
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32)
+}
+
+fn le_u24(bytes: &[u8], at: usize) -> Option<u32> {
+    let b = bytes.get(at..at + 3)?;
+    Some(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16)
+}
+
+/// PNG: the IHDR chunk always comes first, holding big-endian 32-bit width and height.
+fn png_dimensions(content: &[u8]) -> Option<(u32, u32)> {
+    if content.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(content.get(16..20)?.try_into().ok()?);
+    let height = u32::from_be_bytes(content.get(20..24)?.try_into().ok()?);
+    Some((width, height))
+}
+
+/// GIF: the logical screen descriptor follows the signature, with little-endian 16-bit width and height.
+fn gif_dimensions(content: &[u8]) -> Option<(u32, u32)> {
+    Some((le_u16(content, 6)?, le_u16(content, 8)?))
+}
+
+/// WebP: the first chunk is VP8 (lossy), VP8L (lossless) or VP8X (extended), each encoding the size differently.
+fn webp_dimensions(content: &[u8]) -> Option<(u32, u32)> {
+    match content.get(12..16)? {
+        b"VP8 " => {
+            // Key frame start code, then 14-bit width and height (the top 2 bits hold the scale).
+            if content.get(23..26)? != [0x9d, 0x01, 0x2a] {
+                return None;
+            }
+            Some((le_u16(content, 26)? & 0x3fff, le_u16(content, 28)? & 0x3fff))
+        }
+        b"VP8L" => {
+            if *content.get(20)? != 0x2f {
+                return None;
+            }
+            let b = content.get(21..25)?;
+            let width = 1 + (b[0] as u32 | (b[1] as u32 & 0x3f) << 8);
+            let height = 1 + ((b[1] as u32) >> 6 | (b[2] as u32) << 2 | (b[3] as u32 & 0x0f) << 10);
+            Some((width, height))
+        }
+        b"VP8X" => Some((le_u24(content, 24)? + 1, le_u24(content, 27)? + 1)),
+        _ => None,
+    }
+}
+
+/// JPEG: walks the marker segments up to the first start-of-frame, which holds the height and width.
+fn jpeg_dimensions(content: &[u8]) -> Option<(u32, u32)> {
+    let mut at = 2;
+    loop {
+        if *content.get(at)? != 0xff {
+            return None;
+        }
+        // Markers may be preceded by any number of 0xff fill bytes.
+        while *content.get(at + 1)? == 0xff {
+            at += 1;
+        }
+        let marker = *content.get(at + 1)?;
+        at += 2;
+        match marker {
+            // Standalone markers carry no length.
+            0x01 | 0xd0..=0xd8 => continue,
+            // SOF0..SOF15, excluding DHT (c4), JPG (c8) and DAC (cc).
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                return Some((be_u16(content, at + 5)?, be_u16(content, at + 3)?));
+            }
+            // Start of scan or end of image before any frame header.
+            0xd9 | 0xda => return None,
+            _ => at += be_u16(content, at)? as usize,
+        }
+    }
+}
+
+/// SVG: uses the `width`/`height` attributes of the root element when given in pixels,
+/// falling back to the size of the `viewBox`.
+fn svg_dimensions(content: &[u8]) -> Option<(u32, u32)> {
+    let text = String::from_utf8_lossy(&content[..content.len().min(4096)]);
+    let start = text.find("<svg")?;
+    let tag = &text[start..start + text[start..].find('>')?];
+    let attribute = |name: &str| -> Option<&str> {
+        let mut rest = tag;
+        loop {
+            let at = rest.find(name)?;
+            let preceded_by_space = rest[..at].ends_with(char::is_whitespace);
+            rest = &rest[at + name.len()..];
+            // Skip longer attribute names ending in `name`, such as `stroke-width`.
+            let Some(value) = rest.trim_start().strip_prefix('=').filter(|_| preceded_by_space) else {
+                continue;
+            };
+            let value = value.trim_start();
+            let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            let value = &value[1..];
+            return value.find(quote).map(|end| &value[..end]);
+        }
+    };
+    let pixels = |value: &str| -> Option<u32> {
+        let value = value.trim();
+        let number: f64 = value.strip_suffix("px").unwrap_or(value).trim().parse().ok()?;
+        (number.is_finite() && number > 0.0 && number <= u32::MAX as f64).then(|| number.round() as u32)
+    };
+    if let (Some(width), Some(height)) = (
+        attribute("width").and_then(pixels),
+        attribute("height").and_then(pixels),
+    ) {
+        return Some((width, height));
+    }
+    let view_box: Vec<&str> = attribute("viewBox")?
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|s| !s.is_empty())
+        .collect();
+    match view_box.as_slice() {
+        [_, _, width, height] => Some((pixels(width)?, pixels(height)?)),
+        _ => None,
+    }
+}
+
+/// Checks that a media type declared in the metadata has the `type/subtype` form, e.g. `image/webp`.
+pub fn is_valid_media_type(media_type: &str) -> bool {
+    let token = |s: &str| {
+        !s.is_empty()
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+    };
+    match media_type.split_once('/') {
+        Some((kind, subtype)) => token(kind) && token(subtype),
+        None => false,
+    }
+}
+
+/// Downloads every graphic proposal's asset and records what can be learned from it:
+/// the byte size and SHA-256 of the bytes received, the pixel dimensions and, unless the metadata declares one,
+/// the media type sniffed from the magic bytes. The hash is computed here rather than taken from the asset canister,
+/// so the pinned hash does not depend on what the canister claims about its own content.
+/// Unrecognized formats leave the media type and dimensions undetermined; assets that cannot be read leave every
+/// inspected field undetermined. Proposals that already carry a byte size, inspected for a previous version, are skipped.
+pub async fn inspect_graphics(canister_id: Principal, proposals: &mut [GraphicProposal]) {
+    let mut pending: Vec<&mut GraphicProposal> = proposals.iter_mut().filter(|p| p.byte_size.is_none()).collect();
+    for batch in pending.chunks_mut(INSPECTION_BATCH_SIZE) {
+        let contents = join_all(batch.iter().map(|p| assets::fetch_asset(canister_id, &p.asset))).await;
+        for (proposal, content) in batch.iter_mut().zip(contents) {
+            let content = match content {
+                Ok(content) => content,
+                Err(e) => {
+                    ic_cdk::println!("Failed to inspect asset '{}': {}", proposal.asset, e);
+                    continue;
+                }
+            };
+            let sniffed = sniff_media_type(&content);
+            if proposal.media_type.is_none() {
+                proposal.media_type = sniffed.map(String::from);
+            }
+            let dimensions = sniffed.and_then(|media_type| image_dimensions(media_type, &content));
+            proposal.width = dimensions.map(|(width, _)| width);
+            proposal.height = dimensions.map(|(_, height)| height);
+            proposal.byte_size = Some(content.len() as u64);
+            proposal.content_hash = Some(content_hash(&content));
+        }
+    }
+}
+
+/// Re-fetches a registered graphic's asset and reports whether its content still matches
+/// the hash pinned at registration.
+/// Fails with `NotFound` for an unknown OGID and `AssetUnavailable` if the asset cannot be read.
+pub async fn try_verify_graphic(ogid: u64) -> Result<GraphicVerification, OgError> {
+    let graphic = find_graphic(ogid).ok_or_else(|| OgError::NotFound {
+        reason: "Graphic not found".to_string(),
+    })?;
+    let content = assets::fetch_asset(graphic.canister_id, &graphic.asset)
+        .await
+        .map_err(|e| OgError::AssetUnavailable {
+            reason: format!("Failed to retrieve asset '{}': {}", graphic.asset, e),
+        })?;
+    let actual_hash = content_hash(&content);
+    Ok(GraphicVerification {
+        ogid,
+        drifted: graphic.content_hash.as_ref().map(|expected| *expected != actual_hash),
+        expected_hash: graphic.content_hash,
+        actual_hash,
+        byte_size: content.len() as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = b"\x89PNG\r\n\x1a\n".to_vec();
+        bytes.extend(13u32.to_be_bytes());
+        bytes.extend(b"IHDR");
+        bytes.extend(width.to_be_bytes());
+        bytes.extend(height.to_be_bytes());
+        bytes.extend([8, 6, 0, 0, 0]); // bit depth, color type, compression, filter, interlace
+        bytes.extend([0; 4]); // CRC
+        bytes
+    }
+
+    fn gif(width: u16, height: u16) -> Vec<u8> {
+        let mut bytes = b"GIF89a".to_vec();
+        bytes.extend(width.to_le_bytes());
+        bytes.extend(height.to_le_bytes());
+        bytes.extend([0xf7, 0, 0]);
+        bytes
+    }
+
+    fn webp(chunk: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend((4 + 8 + payload.len() as u32).to_le_bytes());
+        bytes.extend(b"WEBP");
+        bytes.extend(chunk);
+        bytes.extend((payload.len() as u32).to_le_bytes());
+        bytes.extend(payload);
+        bytes
+    }
+
+    fn webp_lossy(width: u16, height: u16) -> Vec<u8> {
+        let mut payload = vec![0x30, 0x01, 0x00, 0x9d, 0x01, 0x2a];
+        payload.extend(width.to_le_bytes());
+        payload.extend(height.to_le_bytes());
+        webp(b"VP8 ", &payload)
+    }
+
+    fn webp_lossless(width: u32, height: u32) -> Vec<u8> {
+        let bits = (width - 1) | (height - 1) << 14;
+        let mut payload = vec![0x2f];
+        payload.extend(bits.to_le_bytes());
+        webp(b"VP8L", &payload)
+    }
+
+    fn webp_extended(width: u32, height: u32) -> Vec<u8> {
+        let mut payload = vec![0x10, 0, 0, 0];
+        payload.extend(&(width - 1).to_le_bytes()[..3]);
+        payload.extend(&(height - 1).to_le_bytes()[..3]);
+        webp(b"VP8X", &payload)
+    }
+
+    fn jpeg(width: u16, height: u16) -> Vec<u8> {
+        let mut bytes = vec![0xff, 0xd8];
+        // APP0 (JFIF) segment.
+        bytes.extend([0xff, 0xe0, 0x00, 0x10]);
+        bytes.extend(b"JFIF\0");
+        bytes.extend([1, 1, 0, 0, 1, 0, 1, 0, 0]);
+        // Fill bytes before a DQT segment.
+        bytes.extend([0xff, 0xff, 0xdb, 0x00, 0x03, 0x00]);
+        // SOF2 (progressive) frame header.
+        bytes.extend([0xff, 0xc2, 0x00, 0x11, 0x08]);
+        bytes.extend(height.to_be_bytes());
+        bytes.extend(width.to_be_bytes());
+        bytes.extend([3, 1, 0x22, 0, 2, 0x11, 1, 3, 0x11, 1]);
+        bytes
+    }
+
+    #[test]
+    fn sniffs_media_types() {
+        assert_eq!(sniff_media_type(&png(1, 1)), Some("image/png"));
+        assert_eq!(sniff_media_type(&jpeg(1, 1)), Some("image/jpeg"));
+        assert_eq!(sniff_media_type(&gif(1, 1)), Some("image/gif"));
+        assert_eq!(sniff_media_type(b"GIF87a\x01\x00\x01\x00"), Some("image/gif"));
+        assert_eq!(sniff_media_type(&webp_lossy(1, 1)), Some("image/webp"));
+        assert_eq!(sniff_media_type(b"\0\0\0\x1cftypavif\0\0\0\0"), Some("image/avif"));
+        assert_eq!(
+            sniff_media_type(b"\xef\xbb\xbf<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>"),
+            Some("image/svg+xml")
+        );
+        assert_eq!(sniff_media_type(b"<svg width=\"1\" height=\"1\"/>"), Some("image/svg+xml"));
+    }
+
+    #[test]
+    fn rejects_unknown_and_truncated_signatures() {
+        assert_eq!(sniff_media_type(b""), None);
+        assert_eq!(sniff_media_type(b"\x89PNG\r\n"), None);
+        assert_eq!(sniff_media_type(b"RIFF\0\0\0\0WAVE"), None);
+        assert_eq!(sniff_media_type(b"RIFF\0\0"), None);
+        assert_eq!(sniff_media_type(b"<html><body></body></html>"), None);
+        assert_eq!(sniff_media_type(b"<?xml version=\"1.0\"?><rss/>"), None);
+        assert_eq!(sniff_media_type(&[0x00, 0x9f, 0x92, 0x96]), None);
+    }
+
+    #[test]
+    fn reads_png_dimensions() {
+        assert_eq!(image_dimensions("image/png", &png(640, 480)), Some((640, 480)));
+        assert_eq!(image_dimensions("image/png", &png(640, 480)[..20]), None);
+        let mut corrupt = png(640, 480);
+        corrupt[12..16].copy_from_slice(b"IDAT");
+        assert_eq!(image_dimensions("image/png", &corrupt), None);
+    }
+
+    #[test]
+    fn reads_gif_dimensions() {
+        assert_eq!(image_dimensions("image/gif", &gif(320, 200)), Some((320, 200)));
+        assert_eq!(image_dimensions("image/gif", &gif(320, 200)[..9]), None);
+    }
+
+    #[test]
+    fn reads_webp_dimensions() {
+        assert_eq!(image_dimensions("image/webp", &webp_lossy(1024, 768)), Some((1024, 768)));
+        assert_eq!(image_dimensions("image/webp", &webp_lossless(400, 300)), Some((400, 300)));
+        assert_eq!(image_dimensions("image/webp", &webp_lossless(16384, 1)), Some((16384, 1)));
+        assert_eq!(image_dimensions("image/webp", &webp_extended(5000, 3000)), Some((5000, 3000)));
+    }
+
+    #[test]
+    fn rejects_corrupt_webp_headers() {
+        // The scale bits above the 14-bit size are ignored, but a bad start code is not.
+        let mut scaled = webp_lossy(1024, 768);
+        scaled[27] |= 0x40;
+        assert_eq!(image_dimensions("image/webp", &scaled), Some((1024, 768)));
+        let mut bad_start_code = webp_lossy(1024, 768);
+        bad_start_code[23] = 0;
+        assert_eq!(image_dimensions("image/webp", &bad_start_code), None);
+        let mut bad_signature = webp_lossless(400, 300);
+        bad_signature[20] = 0;
+        assert_eq!(image_dimensions("image/webp", &bad_signature), None);
+        assert_eq!(image_dimensions("image/webp", &webp_extended(5000, 3000)[..28]), None);
+        assert_eq!(image_dimensions("image/webp", &webp(b"ALPH", &[0; 10])), None);
+    }
+
+    #[test]
+    fn reads_jpeg_dimensions() {
+        assert_eq!(image_dimensions("image/jpeg", &jpeg(1920, 1080)), Some((1920, 1080)));
+    }
+
+    #[test]
+    fn rejects_corrupt_jpeg_headers() {
+        let jpeg = jpeg(1920, 1080);
+        // Truncated before and inside the frame header.
+        assert_eq!(image_dimensions("image/jpeg", &jpeg[..20]), None);
+        assert_eq!(image_dimensions("image/jpeg", &jpeg[..jpeg.len() - 14]), None);
+        // Start of scan before any frame header.
+        assert_eq!(image_dimensions("image/jpeg", &[0xff, 0xd8, 0xff, 0xda, 0x00, 0x02]), None);
+        // Garbage where a marker is expected.
+        assert_eq!(image_dimensions("image/jpeg", &[0xff, 0xd8, 0x00, 0x00, 0x00]), None);
+        // A DHT segment (0xc4) is not a frame header.
+        assert_eq!(image_dimensions("image/jpeg", &[0xff, 0xd8, 0xff, 0xc4, 0x00, 0x02]), None);
+    }
+
+    #[test]
+    fn reads_svg_dimensions() {
+        let svg = |s: &str| image_dimensions("image/svg+xml", s.as_bytes());
+        assert_eq!(svg(r#"<svg xmlns="http://www.w3.org/2000/svg" width="120" height="80">"#), Some((120, 80)));
+        assert_eq!(svg(r#"<svg width='120px' height = '80.4px'>"#), Some((120, 80)));
+        assert_eq!(svg(r#"<svg viewBox="0 0 300 150">"#), Some((300, 150)));
+        assert_eq!(svg(r#"<svg viewBox="0,0,300,150" width="100%" height="100%">"#), Some((300, 150)));
+        // `stroke-width` must not be mistaken for `width`.
+        assert_eq!(svg(r#"<svg stroke-width="3" viewBox="0 0 24 24">"#), Some((24, 24)));
+        assert_eq!(
+            svg(r#"<svg stroke-width="3" width="48" height="32" viewBox="0 0 24 24">"#),
+            Some((48, 32))
+        );
+    }
+
+    #[test]
+    fn rejects_unsized_or_corrupt_svg() {
+        let svg = |s: &str| image_dimensions("image/svg+xml", s.as_bytes());
+        assert_eq!(svg(r#"<svg xmlns="http://www.w3.org/2000/svg">"#), None);
+        assert_eq!(svg(r#"<svg width="120" height="80""#), None);
+        assert_eq!(svg(r#"<svg width="-1" height="80">"#), None);
+        assert_eq!(svg(r#"<svg viewBox="0 0 300">"#), None);
+        assert_eq!(svg(r#"<svg width=120 height=80>"#), None);
+    }
+
+    #[test]
+    fn ignores_unparsable_formats() {
+        assert_eq!(image_dimensions("image/avif", b"\0\0\0\x1cftypavif"), None);
+        assert_eq!(image_dimensions("image/png", b""), None);
+    }
+
+    #[test]
+    fn validates_media_types() {
+        assert!(is_valid_media_type("image/webp"));
+        assert!(is_valid_media_type("image/svg+xml"));
+        assert!(!is_valid_media_type("image"));
+        assert!(!is_valid_media_type("image/"));
+        assert!(!is_valid_media_type("image/png; charset=binary"));
+    }
+}
@@ -0,0 +1,55 @@
+// File: src/og_backend/src/memory.rs
+
+use ic_cdk::api::stable::{stable_read, stable_size};
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::DefaultMemoryImpl;
+use std::cell::RefCell;
+
+/// A virtual memory region handed out by the memory manager.
+pub type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+/// Memory region backing the registered collections map.
+pub const COLLECTIONS_MEMORY_ID: MemoryId = MemoryId::new(0);
+/// Memory region backing the registered graphics map.
+pub const GRAPHICS_MEMORY_ID: MemoryId = MemoryId::new(1);
+/// Memory region backing the role assignments map.
+pub const ROLES_MEMORY_ID: MemoryId = MemoryId::new(2);
+/// Memory region backing the index of the change feed log.
+pub const CHANGES_INDEX_MEMORY_ID: MemoryId = MemoryId::new(3);
+/// Memory region backing the entries of the change feed log.
+pub const CHANGES_DATA_MEMORY_ID: MemoryId = MemoryId::new(4);
+/// Memory region backing the index of the ICRC-3 block log.
+pub const BLOCKS_INDEX_MEMORY_ID: MemoryId = MemoryId::new(5);
+/// Memory region backing the blocks of the ICRC-3 block log.
+pub const BLOCKS_DATA_MEMORY_ID: MemoryId = MemoryId::new(6);
+/// Memory region backing the past versions of collections.
+pub const COLLECTION_HISTORY_MEMORY_ID: MemoryId = MemoryId::new(7);
+/// Memory region backing the past versions of graphics.
+pub const GRAPHIC_HISTORY_MEMORY_ID: MemoryId = MemoryId::new(8);
+/// Memory region backing the flag recording that records stored before versions were kept have been seeded.
+pub const HISTORY_SEEDED_MEMORY_ID: MemoryId = MemoryId::new(9);
+
+// The memory manager is initialized lazily on first access. It must not be touched
+// before a legacy `stable_save` snapshot has been restored, as initializing it
+// overwrites the beginning of stable memory.
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+}
+
+/// Returns the virtual memory region for the given memory ID.
+pub fn get_memory(id: MemoryId) -> Memory {
+    MEMORY_MANAGER.with(|manager| manager.borrow().get(id))
+}
+
+/// Returns true if stable memory still holds a snapshot written by `storage::stable_save`.
+/// Such snapshots are Candid-encoded and therefore start with the "DIDL" magic bytes,
+/// whereas the memory manager layout starts with "MGR".
+pub fn has_legacy_layout() -> bool {
+    if stable_size() == 0 {
+        return false;
+    }
+    let mut magic = [0u8; 4];
+    stable_read(0, &mut magic);
+    &magic == b"DIDL"
+}
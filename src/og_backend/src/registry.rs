@@ -1,15 +1,34 @@
 // File: src/og_backend/src/registry.rs
 
-use candid::{CandidType, Principal};
+use crate::memory::{get_memory, Memory, COLLECTIONS_MEMORY_ID, GRAPHICS_MEMORY_ID};
+use crate::assets;
+use crate::attributes::{self, Attribute};
+use crate::certification;
+use crate::changes::{self, Change};
+use crate::diff;
+use crate::history;
+use crate::icrc3;
+use crate::media;
+use crate::search;
+use crate::sync::{self, SyncState};
+use crate::tags;
+use crate::validation;
+use candid::{CandidType, Decode, Encode, Principal};
 use ic_cdk::api::time;
-use ic_cdk::call;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{StableBTreeMap, Storable};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sha2::{Digest, Sha256};
+use std::borrow::Cow;
 use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use time::OffsetDateTime;
 use time::macros::format_description;
 
+/// Path of the metadata file read from an asset canister when the collection does not configure one.
+pub const DEFAULT_METADATA_PATH: &str = "/og_metadata.json";
+
 /// Persistent data for a registered graphic, with mandatory title.
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct IndexedGraphic {
@@ -21,6 +40,25 @@ pub struct IndexedGraphic {
     pub registration_timestamp: String,
     pub update_timestamp: Option<String>, // Update timestamp field
     pub canister_id: Principal,
+    pub attributes: Option<Vec<Attribute>>, // None for graphics registered before attributes were parsed
+    pub tags: Option<Vec<String>>,          // None for graphics registered before tags were parsed
+    pub media_type: Option<String>,         // Declared in the metadata or sniffed from the asset; None if unknown
+    pub width: Option<u32>,                 // Pixel dimensions read from the image header; None if unknown
+    pub height: Option<u32>,
+    pub byte_size: Option<u64>,             // None for graphics registered before assets were inspected
+    pub content_hash: Option<String>,       // Hex SHA-256 of the asset bytes; None for graphics registered before hashes were pinned
+}
+
+impl IndexedGraphic {
+    /// Returns this graphic's attributes, empty for graphics registered before attributes were parsed.
+    pub fn attributes(&self) -> &[Attribute] {
+        self.attributes.as_deref().unwrap_or_default()
+    }
+
+    /// Returns this graphic's normalized tags, empty for graphics registered before tags were parsed.
+    pub fn tags(&self) -> &[String] {
+        self.tags.as_deref().unwrap_or_default()
+    }
 }
 
 /// Persistent data for a registered collection, with mandatory title.
@@ -34,30 +72,214 @@ pub struct IndexedCollection {
     pub graphics: Vec<u64>,
     pub registration_timestamp: String,
     pub update_timestamp: Option<String>, // Update timestamp field
+    pub metadata_path: Option<String>,    // None for collections registered before paths were configurable
+    pub tags: Option<Vec<String>>,        // None for collections registered before tags were parsed
+    pub canister_id: Option<Principal>,   // Asset canister the metadata is read from; None for older records
+    pub sync: Option<SyncState>,          // Automatic resync settings and status; None if disabled
+}
+
+impl IndexedCollection {
+    /// Returns the path of this collection's metadata file within its asset canister.
+    pub fn metadata_path(&self) -> &str {
+        self.metadata_path.as_deref().unwrap_or(DEFAULT_METADATA_PATH)
+    }
+
+    /// Returns this collection's normalized tags, empty for collections registered before tags were parsed.
+    pub fn tags(&self) -> &[String] {
+        self.tags.as_deref().unwrap_or_default()
+    }
+
+    /// Returns the asset canister this collection was registered from. Collections registered before the
+    /// canister was recorded fall back to the canister of their first graphic.
+    pub fn source_canister(&self) -> Option<Principal> {
+        self.canister_id.or_else(|| {
+            self.graphics
+                .first()
+                .and_then(|ogid| find_graphic(*ogid))
+                .map(|graphic| graphic.canister_id)
+        })
+    }
+
+    /// Rejects updates from an asset canister other than the one the collection was registered from,
+    /// which would silently repoint its graphics.
+    pub fn check_source_canister(&self, canister_id: Principal) -> Result<(), String> {
+        match self.source_canister() {
+            Some(source) if source != canister_id => Err(format!(
+                "Collection {} is registered from asset canister {}, not {}",
+                self.collection_id, source, canister_id
+            )),
+            _ => Ok(()),
+        }
+    }
 }
 
-/// The complete persistent state for the canister.
+/// Resolves an optional, caller-supplied metadata path, falling back to `DEFAULT_METADATA_PATH`.
+/// Paths must be absolute within the asset canister, e.g. `/2021/og_metadata.json`.
+pub fn resolve_metadata_path(metadata_path: Option<String>) -> Result<String, String> {
+    match metadata_path {
+        None => Ok(DEFAULT_METADATA_PATH.to_string()),
+        Some(path) if path.starts_with('/') && path.len() > 1 && !path.contains(char::is_whitespace) => Ok(path),
+        Some(path) => Err(format!("Invalid metadata path '{}': must be an absolute asset path", path)),
+    }
+}
+
+impl Storable for IndexedGraphic {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("failed to encode IndexedGraphic"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("failed to decode IndexedGraphic")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl Storable for IndexedCollection {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("failed to encode IndexedCollection"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("failed to decode IndexedCollection")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// A snapshot of the complete persistent state for the canister.
+/// This is also the layout previously written by `storage::stable_save` in `pre_upgrade`,
+/// and is used to migrate such snapshots into the stable maps.
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
 pub struct RegistryState {
     pub registered_collections: Vec<IndexedCollection>,
     pub registered_graphics: Vec<IndexedGraphic>,
 }
 
-// Global persistent state stored in stable memory.
+/// In-memory lookup structures derived from the stable maps, serving the read path
+/// without decoding or cloning unrelated records.
+/// The index is not persisted; `rebuild_index` recreates it after an upgrade.
+#[derive(Default)]
+pub struct RegistryIndex {
+    /// collection_id -> OGIDs of the collection's graphics, in metadata order.
+    collection_graphics: BTreeMap<u64, Vec<u64>>,
+    /// (registration timestamp, collection_id) of every collection, in registration order.
+    by_registration: BTreeSet<(String, u64)>,
+    /// (lowercased title, collection_id) of every collection, in title order.
+    by_title: BTreeSet<(String, u64)>,
+}
+
+impl RegistryIndex {
+    fn insert_collection(&mut self, collection: &IndexedCollection) {
+        let id = collection.collection_id;
+        self.collection_graphics.insert(id, collection.graphics.clone());
+        self.by_registration.insert((collection.registration_timestamp.clone(), id));
+        self.by_title.insert((collection.title.to_lowercase(), id));
+    }
+
+    fn remove_collection(&mut self, collection: &IndexedCollection) {
+        let id = collection.collection_id;
+        self.collection_graphics.remove(&id);
+        self.by_registration.remove(&(collection.registration_timestamp.clone(), id));
+        self.by_title.remove(&(collection.title.to_lowercase(), id));
+    }
+
+    /// Returns the number of registered collections.
+    pub fn collection_count(&self) -> u64 {
+        self.collection_graphics.len() as u64
+    }
+
+    /// Returns the registered collection IDs in ascending order.
+    pub fn collection_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.collection_graphics.keys().copied()
+    }
+
+    /// Returns the registered collection IDs, oldest registration first.
+    /// Collections registered within the same second are ordered by ID.
+    pub fn collection_ids_by_registration(&self) -> impl DoubleEndedIterator<Item = u64> + '_ {
+        self.by_registration.iter().map(|(_, id)| *id)
+    }
+
+    /// Returns the registered collection IDs ordered by title, ignoring case.
+    pub fn collection_ids_by_title(&self) -> impl Iterator<Item = u64> + '_ {
+        self.by_title.iter().map(|(_, id)| *id)
+    }
+
+    /// Returns the OGIDs of the given collection's graphics, if the collection is registered.
+    pub fn graphics_of(&self, collection_id: u64) -> Option<&[u64]> {
+        self.collection_graphics.get(&collection_id).map(Vec::as_slice)
+    }
+}
+
+// Global persistent state stored in stable memory, keyed by collection_id and ogid,
+// plus the in-memory index derived from it.
 thread_local! {
-    static STATE: RefCell<RegistryState> = RefCell::new(RegistryState::default());
+    static COLLECTIONS: RefCell<StableBTreeMap<u64, IndexedCollection, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(COLLECTIONS_MEMORY_ID)));
+    static GRAPHICS: RefCell<StableBTreeMap<u64, IndexedGraphic, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(GRAPHICS_MEMORY_ID)));
+    static INDEX: RefCell<RegistryIndex> = RefCell::new(RegistryIndex::default());
 }
 
-/// Returns a clone of the current persistent state.
-pub fn get_state() -> RegistryState {
-    STATE.with(|state| state.borrow().clone())
+/// Runs `f` with borrowed access to the in-memory registry index.
+pub fn with_index<R>(f: impl FnOnce(&RegistryIndex) -> R) -> R {
+    INDEX.with(|index| f(&index.borrow()))
+}
+
+/// Recreates the in-memory index, the search index, the tag index, the sync schedule
+/// and the certified tree from the stable maps.
+/// Records stored before versions were kept are given a first version on the first rebuild only;
+/// the history is persisted, so later upgrades skip it.
+pub fn rebuild_index() {
+    let seed_history = !history::is_seeded();
+    certification::clear();
+    icrc3::certify_tip();
+    search::clear();
+    tags::clear();
+    sync::clear_schedule();
+    let mut registry_index = RegistryIndex::default();
+    COLLECTIONS.with(|c| {
+        GRAPHICS.with(|g| {
+            let g = g.borrow();
+            for (_, collection) in c.borrow().iter() {
+                let graphics: Vec<IndexedGraphic> =
+                    collection.graphics.iter().filter_map(|ogid| g.get(ogid)).collect();
+                search::index_records(&collection, &graphics);
+                tags::index_records(&collection, &graphics);
+                sync::schedule_collection(&collection);
+                certification::certify_records(&collection, &graphics);
+                if seed_history {
+                    history::seed_records(&collection, &graphics);
+                }
+                registry_index.insert_collection(&collection);
+            }
+        })
+    });
+    INDEX.with(|index| {
+        *index.borrow_mut() = registry_index;
+    });
+    if seed_history {
+        history::mark_seeded();
+    }
 }
 
 /// Replaces the current persistent state.
 pub fn set_state(new_state: RegistryState) {
-    STATE.with(|state| {
-        *state.borrow_mut() = new_state;
+    COLLECTIONS.with(|c| {
+        let mut c = c.borrow_mut();
+        c.clear_new();
+        for collection in new_state.registered_collections {
+            c.insert(collection.collection_id, collection);
+        }
+    });
+    GRAPHICS.with(|g| {
+        let mut g = g.borrow_mut();
+        g.clear_new();
+        for graphic in new_state.registered_graphics {
+            g.insert(graphic.ogid, graphic);
+        }
     });
+    rebuild_index();
 }
 
 /// Initializes the persistent state.
@@ -65,20 +287,12 @@ pub fn init_state() {
     set_state(RegistryState::default());
 }
 
-/// Makes an inter-canister call to the asset canister's "retrieve" method.
-/// Expects the asset canister to return a tuple containing a blob (Vec<u8>).
+/// Fetches a file from the asset canister, through its custom "retrieve" method or,
+/// failing that, the standard asset canister interface.
 /// Returns the metadata blob as a UTF-8 string.
 pub async fn retrieve(canister_id: Principal, asset: &str) -> Result<String, String> {
-    let result: Result<(Vec<u8>,), _> = call(canister_id, "retrieve", (asset,)).await;
-    match result {
-        Ok((metadata_blob,)) => {
-            String::from_utf8(metadata_blob).map_err(|e| format!("Invalid UTF-8 data: {}", e))
-        }
-        Err((code, msg)) => Err(format!(
-            "Inter-canister call failed with code {:?}: {:?}",
-            code, msg
-        )),
-    }
+    let metadata_blob = assets::fetch_asset(canister_id, asset).await?;
+    String::from_utf8(metadata_blob).map_err(|e| format!("Invalid UTF-8 data: {}", e))
 }
 
 /// Parses the metadata blob (JSON string) to extract collection info.
@@ -101,12 +315,14 @@ pub fn parse_collection(metadata_blob: &str) -> Result<CollectionProposal, Strin
         .get("external_link")
         .and_then(|e| e.as_str())
         .map(String::from);
+    let tags = tags::parse_tags(collection).map_err(|e| format!("Invalid tags in collection: {}", e))?;
 
     Ok(CollectionProposal {
         title,
         description,
         artist,
         external_link,
+        tags,
     })
 }
 
@@ -141,10 +357,29 @@ pub fn parse_graphics(metadata_blob: &str) -> Result<Vec<GraphicProposal>, Strin
             .and_then(|d| d.as_str())
             .map(String::from);
 
+        let attributes = attributes::parse_attributes(graphic)
+            .map_err(|e| format!("Invalid attributes in graphic '{}': {}", asset, e))?;
+        let tags = tags::parse_tags(graphic)
+            .map_err(|e| format!("Invalid tags in graphic '{}': {}", asset, e))?;
+
+        // Media type is optional; when absent it is sniffed from the asset during registration.
+        let media_type = match graphic.get("media_type") {
+            None => None,
+            Some(Value::String(m)) if media::is_valid_media_type(m) => Some(m.to_ascii_lowercase()),
+            Some(_) => return Err(format!("Invalid 'media_type' in graphic '{}'", asset)),
+        };
+
         proposals.push(GraphicProposal {
             asset,
             title,
             description,
+            attributes,
+            tags,
+            media_type,
+            width: None,
+            height: None,
+            byte_size: None,
+            content_hash: None,
         });
     }
     Ok(proposals)
@@ -162,43 +397,93 @@ pub fn get_current_timestamp() -> String {
     datetime.format(&format).expect("Failed to format datetime")
 }
 
-/// Generates a unique on-chain graphic ID (OGID) based on the canister ID, asset, and current time.
-pub fn generate_ogid(canister_id: Principal, asset: &str) -> u64 {
+/// Derives a unique on-chain graphic ID (OGID) from the canister ID and asset path.
+/// The derivation is deterministic, so the same asset always maps to the same OGID.
+/// If the candidate is already registered or in `reserved`, a counter is appended to the
+/// hash input until a free ID is found.
+pub fn generate_ogid(canister_id: Principal, asset: &str, reserved: &HashSet<u64>) -> u64 {
+    let mut attempt: u64 = 0;
     loop {
-        let ts = get_current_timestamp();
         let mut hasher = Sha256::new();
-        hasher.update(format!("{}{}{}", canister_id.to_text(), asset, ts));
+        if attempt == 0 {
+            hasher.update(format!("{}{}", canister_id.to_text(), asset));
+        } else {
+            hasher.update(format!("{}{}{}", canister_id.to_text(), asset, attempt));
+        }
         let result = hasher.finalize();
         let bytes = &result[..8];
         let candidate = u64::from_be_bytes(bytes.try_into().unwrap()) / 10000;
-        let is_unique = STATE.with(|state| {
-            let state = state.borrow();
-            !state.registered_graphics.iter().any(|g| g.ogid == candidate)
-        });
+        let is_unique = !reserved.contains(&candidate)
+            && GRAPHICS.with(|g| !g.borrow().contains_key(&candidate));
         if is_unique {
             return candidate;
         }
+        attempt += 1;
     }
 }
 
-/// Generates a unique collection ID based on the canister ID and current time.
-pub fn generate_collection_id(canister_id: Principal) -> u64 {
+/// Generates a unique collection ID based on the canister ID, the metadata path and the current time.
+/// The time does not change within a message, so collisions are resolved by rehashing with an attempt counter.
+pub fn generate_collection_id(canister_id: Principal, metadata_path: &str) -> u64 {
+    let ts = get_current_timestamp();
+    let mut attempt: u64 = 0;
     loop {
-        let ts = get_current_timestamp();
         let mut hasher = Sha256::new();
-        hasher.update(format!("{}{}{}", canister_id.to_text(), "seed", ts));
+        if attempt == 0 {
+            hasher.update(format!("{}{}{}{}", canister_id.to_text(), metadata_path, "seed", ts));
+        } else {
+            hasher.update(format!("{}{}{}{}{}", canister_id.to_text(), metadata_path, "seed", ts, attempt));
+        }
         let result = hasher.finalize();
         let bytes = &result[..8];
         let candidate = u64::from_be_bytes(bytes.try_into().unwrap()) / 10000;
-        if find_collection(candidate).is_none() {
+        if !COLLECTIONS.with(|c| c.borrow().contains_key(&candidate)) {
             return candidate;
         }
+        attempt += 1;
     }
 }
 
+/// Finds the old graphic a proposal for `asset` carries over, skipping OGIDs already claimed
+/// by an earlier proposal (so a duplicated asset path is treated as a new graphic).
+pub fn match_old_graphic<'a>(
+    old_graphics: &'a [IndexedGraphic],
+    asset: &str,
+    assigned: &HashSet<u64>,
+) -> Option<&'a IndexedGraphic> {
+    old_graphics
+        .iter()
+        .find(|g| g.asset == asset)
+        .filter(|g| !assigned.contains(&g.ogid))
+}
+
+/// Returns true if any modifiable field of a graphic differs from the proposal: its title, description,
+/// attributes, tags or the inspected media type, dimensions, size and content hash.
+pub fn graphic_differs(old: &IndexedGraphic, proposal: &GraphicProposal) -> bool {
+    old.title != proposal.title
+        || old.description != proposal.description
+        || old.attributes() != proposal.attributes.as_slice()
+        || old.tags() != proposal.tags.as_slice()
+        || old.media_type != proposal.media_type
+        || old.width != proposal.width
+        || old.height != proposal.height
+        || old.byte_size != proposal.byte_size
+        || old.content_hash != proposal.content_hash
+}
+
+/// Returns true if any collection field differs from the proposal.
+pub fn collection_differs(old: &IndexedCollection, proposal: &CollectionProposal) -> bool {
+    old.title != proposal.title
+        || old.description != proposal.description
+        || old.artist != proposal.artist
+        || old.external_link != proposal.external_link
+        || old.tags() != proposal.tags.as_slice()
+}
+
 /// Generates indexed graphics from the provided graphic proposals using a differential update.
-/// For each new graphic proposal, if an old record with the same asset exists, its registration timestamp is preserved.
-/// The function compares modifiable fields (title and description) and only updates the record (with a new update timestamp)
+/// For each new graphic proposal, if an old record with the same asset exists, its OGID and registration timestamp are preserved.
+/// The function compares modifiable fields (title, description, attributes, tags and
+/// the media type, dimensions, size and content hash of the asset) and only updates the record (with a new update timestamp)
 /// if differences are found. It returns both the new vector and a boolean indicating whether any graphic was changed.
 pub fn index_graphics_differential(
     canister_id: Principal,
@@ -208,34 +493,27 @@ pub fn index_graphics_differential(
 ) -> Result<(Vec<IndexedGraphic>, bool), String> {
     let mut changed = false;
     let mut indexed = Vec::new();
+    let mut assigned = HashSet::new();
     for proposal in proposals {
-        let final_ogid = generate_ogid(canister_id, &proposal.asset);
-        // If an old graphic exists with the same asset, preserve its registration timestamp.
-        let reg_ts = if let Some(ref old) = old_graphics {
-            if let Some(old_g) = old.iter().find(|g| g.asset == proposal.asset) {
-                old_g.registration_timestamp.clone()
-            } else {
-                get_current_timestamp()
-            }
-        } else {
-            get_current_timestamp()
+        // If an old graphic exists with the same asset, keep its OGID so existing links stay valid.
+        let old_match = old_graphics
+            .as_deref()
+            .and_then(|old| match_old_graphic(old, &proposal.asset, &assigned));
+        let final_ogid = match old_match {
+            Some(old_g) => old_g.ogid,
+            None => generate_ogid(canister_id, &proposal.asset, &assigned),
         };
+        assigned.insert(final_ogid);
+        // If an old graphic exists with the same asset, preserve its registration timestamp.
+        let reg_ts = old_match
+            .map(|old_g| old_g.registration_timestamp.clone())
+            .unwrap_or_else(get_current_timestamp);
         // Check for differences in modifiable fields.
-        let (update_ts, is_different) = if let Some(ref old) = old_graphics {
-            if let Some(old_g) = old.iter().find(|g| g.asset == proposal.asset) {
-                if old_g.title == proposal.title && old_g.description == proposal.description {
-                    // No changes; keep old update timestamp.
-                    (old_g.update_timestamp.clone(), false)
-                } else {
-                    // Changed fields; update timestamp now.
-                    (Some(get_current_timestamp()), true)
-                }
-            } else {
-                // New graphic.
-                (Some(get_current_timestamp()), true)
-            }
-        } else {
-            (Some(get_current_timestamp()), true)
+        let (update_ts, is_different) = match old_match {
+            // No changes; keep old update timestamp.
+            Some(old_g) if !graphic_differs(old_g, &proposal) => (old_g.update_timestamp.clone(), false),
+            // Changed fields or new graphic; update timestamp now.
+            _ => (Some(get_current_timestamp()), true),
         };
         if is_different {
             changed = true;
@@ -249,6 +527,13 @@ pub fn index_graphics_differential(
             registration_timestamp: reg_ts,
             update_timestamp: update_ts,
             canister_id,
+            attributes: Some(proposal.attributes),
+            tags: Some(proposal.tags),
+            media_type: proposal.media_type,
+            width: proposal.width,
+            height: proposal.height,
+            byte_size: proposal.byte_size,
+            content_hash: proposal.content_hash,
         };
         indexed.push(indexed_graphic);
     }
@@ -257,13 +542,16 @@ pub fn index_graphics_differential(
 
 /// Generates an indexed collection record from the collection proposal and indexed graphics.
 /// The old registration timestamp is preserved if available; a new update timestamp is generated.
+/// Automatic resync is disabled on new records; updates carry the previous sync settings over.
 pub fn index_collection(
     proposal: CollectionProposal,
     indexed_graphics: Vec<IndexedGraphic>,
     collection_id: u64,
     old_registration: Option<String>,
+    metadata_path: String,
+    canister_id: Principal,
 ) -> Result<IndexedCollection, String> {
-    let reg_ts = old_registration.unwrap_or_else(|| get_current_timestamp());
+    let reg_ts = old_registration.unwrap_or_else(get_current_timestamp);
     let update_ts = Some(get_current_timestamp());
     Ok(IndexedCollection {
         collection_id,
@@ -274,6 +562,10 @@ pub fn index_collection(
         graphics: indexed_graphics.iter().map(|g| g.ogid).collect(),
         registration_timestamp: reg_ts,
         update_timestamp: update_ts,
+        metadata_path: Some(metadata_path),
+        tags: Some(proposal.tags),
+        canister_id: Some(canister_id),
+        sync: None,
     })
 }
 
@@ -282,47 +574,174 @@ pub fn store_state(
     indexed_graphics: Vec<IndexedGraphic>,
     indexed_collection: IndexedCollection,
 ) -> Result<(), String> {
-    STATE.with(|state| {
-        let mut s = state.borrow_mut();
-        s.registered_graphics.extend(indexed_graphics);
-        s.registered_collections.push(indexed_collection);
-        Ok(())
-    })
+    search::index_records(&indexed_collection, &indexed_graphics);
+    tags::index_records(&indexed_collection, &indexed_graphics);
+    sync::schedule_collection(&indexed_collection);
+    certification::certify_records(&indexed_collection, &indexed_graphics);
+    GRAPHICS.with(|g| {
+        let mut g = g.borrow_mut();
+        for graphic in indexed_graphics {
+            g.insert(graphic.ogid, graphic);
+        }
+    });
+    INDEX.with(|index| {
+        index.borrow_mut().insert_collection(&indexed_collection);
+    });
+    COLLECTIONS.with(|c| {
+        c.borrow_mut()
+            .insert(indexed_collection.collection_id, indexed_collection);
+    });
+    Ok(())
 }
 
 /// Finds and returns a registered collection by collection_id, if it exists.
 pub fn find_collection(collection_id: u64) -> Option<IndexedCollection> {
-    STATE.with(|state| {
-        let state = state.borrow();
-        state.registered_collections.iter().find(|c| c.collection_id == collection_id).cloned()
+    COLLECTIONS.with(|c| c.borrow().get(&collection_id))
+}
+
+/// Finds and returns a registered graphic by OGID, if it exists.
+pub fn find_graphic(ogid: u64) -> Option<IndexedGraphic> {
+    GRAPHICS.with(|g| g.borrow().get(&ogid))
+}
+
+/// Returns the registered graphics of a collection, in metadata order.
+pub fn collection_graphics(collection: &IndexedCollection) -> Vec<IndexedGraphic> {
+    GRAPHICS.with(|g| {
+        let g = g.borrow();
+        collection
+            .graphics
+            .iter()
+            .filter_map(|ogid| g.get(ogid))
+            .collect()
     })
 }
 
+/// Removes a collection record and all graphics it references from persistent state.
+fn remove_collection_records(collection: &IndexedCollection) {
+    GRAPHICS.with(|g| {
+        let mut g = g.borrow_mut();
+        for ogid in &collection.graphics {
+            g.remove(ogid);
+        }
+    });
+    COLLECTIONS.with(|c| {
+        c.borrow_mut().remove(&collection.collection_id);
+    });
+    INDEX.with(|index| {
+        index.borrow_mut().remove_collection(collection);
+    });
+    search::remove_records(collection.collection_id, &collection.graphics);
+    tags::remove_records(collection.collection_id, &collection.graphics);
+    sync::unschedule_collection(collection.collection_id);
+    certification::remove_records(collection.collection_id, &collection.graphics);
+}
+
+/// Replaces the sync settings and status stored on a collection record and reschedules it.
+pub fn set_collection_sync(collection_id: u64, sync: Option<SyncState>) -> Result<(), String> {
+    let mut collection = find_collection(collection_id).ok_or("Collection not found".to_string())?;
+    collection.sync = sync;
+    sync::schedule_collection(&collection);
+    // The sync status is part of the served `CollectionInfo`, so the collection is re-certified.
+    certification::certify_records(&collection, &[]);
+    COLLECTIONS.with(|c| {
+        c.borrow_mut().insert(collection_id, collection);
+    });
+    Ok(())
+}
+
 /// Atomically unregisters a collection and its associated graphics from persistent state.
-pub fn try_unregister_collection(collection_id: u64) -> Result<(), String> {
-    if find_collection(collection_id).is_some() {
-        STATE.with(|state| {
-            let mut s = state.borrow_mut();
-            s.registered_collections.retain(|c| c.collection_id != collection_id);
-            s.registered_graphics.retain(|g| g.collection_id != collection_id);
-            Ok(())
-        })
+/// The removal is recorded in the change feed on behalf of `caller`.
+pub fn try_unregister_collection(collection_id: u64, caller: Principal) -> Result<(), String> {
+    if let Some(collection) = find_collection(collection_id) {
+        remove_collection_records(&collection);
+        let removed = collection
+            .graphics
+            .iter()
+            .map(|&ogid| Change::GraphicRemoved { collection_id, ogid });
+        changes::record(caller, removed.chain([Change::CollectionUnregistered { collection_id }]));
+        icrc3::record_unregistration(caller, collection_id);
+        let now = time();
+        history::record_collection(collection_id, now, None);
+        for ogid in &collection.graphics {
+            history::record_graphic(*ogid, now, None);
+        }
+        Ok(())
     } else {
         Err("Collection not registered".to_string())
     }
 }
 
-/// The asynchronous function that implements the entire registration process.
-/// It retrieves the metadata, parses proposals, generates new records, and updates the persistent state atomically.
-pub async fn try_register_collection(canister_id: Principal) -> Result<(), String> {
-    let metadata_blob = retrieve(canister_id, "/og_metadata.json")
-        .await
-        .map_err(|e| format!("Failed to retrieve metadata: {}", e))?;
-    let collection_proposal = parse_collection(&metadata_blob)
+/// Validates a metadata blob and parses it into collection and graphic proposals.
+/// Metadata with any issue the `validate_collection` dry run would report is rejected.
+pub fn parse_proposals(metadata_blob: &str) -> Result<(CollectionProposal, Vec<GraphicProposal>), String> {
+    validation::check_metadata(metadata_blob)?;
+    let collection_proposal = parse_collection(metadata_blob)
         .map_err(|e| format!("Failed to parse collection: {}", e))?;
-    let graphic_proposals = parse_graphics(&metadata_blob)
+    let graphic_proposals = parse_graphics(metadata_blob)
         .map_err(|e| format!("Failed to parse graphics: {}", e))?;
-    let collection_id = generate_collection_id(canister_id);
+    Ok((collection_proposal, graphic_proposals))
+}
+
+/// Retrieves the metadata file at `metadata_path` from an asset canister, validates it and parses it into
+/// collection and graphic proposals, without inspecting the graphics' assets.
+async fn fetch_metadata_proposals(
+    canister_id: Principal,
+    metadata_path: &str,
+) -> Result<(CollectionProposal, Vec<GraphicProposal>), String> {
+    let metadata_blob = retrieve(canister_id, metadata_path)
+        .await
+        .map_err(|e| format!("Failed to retrieve metadata: {}", e))?;
+    parse_proposals(&metadata_blob)
+}
+
+/// Retrieves the metadata file at `metadata_path` from an asset canister, parses it into
+/// collection and graphic proposals and inspects every graphic's asset.
+pub async fn fetch_proposals(
+    canister_id: Principal,
+    metadata_path: &str,
+) -> Result<(CollectionProposal, Vec<GraphicProposal>), String> {
+    let (collection_proposal, mut graphic_proposals) = fetch_metadata_proposals(canister_id, metadata_path).await?;
+    media::inspect_graphics(canister_id, &mut graphic_proposals).await;
+    Ok((collection_proposal, graphic_proposals))
+}
+
+/// Copies the inspected fields of old graphics onto the not yet inspected proposals for the same asset path,
+/// so that `media::inspect_graphics` only fetches the assets of new graphics, or so that an asset which could not
+/// be read again keeps its previous inspection.
+/// A media type declared in the metadata takes precedence over the one recorded before.
+fn carry_over_inspection(old_graphics: &[IndexedGraphic], proposals: &mut [GraphicProposal]) {
+    let mut assigned = HashSet::new();
+    for proposal in proposals.iter_mut() {
+        let Some(old) = match_old_graphic(old_graphics, &proposal.asset, &assigned) else {
+            continue;
+        };
+        assigned.insert(old.ogid);
+        if old.byte_size.is_none() || proposal.byte_size.is_some() {
+            continue;
+        }
+        if proposal.media_type.is_none() {
+            proposal.media_type = old.media_type.clone();
+        }
+        proposal.width = old.width;
+        proposal.height = old.height;
+        proposal.byte_size = old.byte_size;
+        proposal.content_hash = old.content_hash.clone();
+    }
+}
+
+/// The asynchronous function that implements the entire registration process.
+/// It retrieves the metadata, parses proposals, generates new records, and updates the persistent state atomically.
+/// The metadata is read from `metadata_path` (or `DEFAULT_METADATA_PATH`), which is persisted for subsequent updates.
+/// Returns the collection ID assigned to the newly registered collection.
+/// The registration is recorded in the change feed on behalf of `caller`.
+pub async fn try_register_collection(
+    canister_id: Principal,
+    metadata_path: Option<String>,
+    caller: Principal,
+) -> Result<u64, String> {
+    let metadata_path = resolve_metadata_path(metadata_path)?;
+    let (collection_proposal, graphic_proposals) = fetch_proposals(canister_id, &metadata_path).await?;
+    let collection_id = generate_collection_id(canister_id, &metadata_path);
     let indexed_graphics = index_graphics_differential(canister_id, graphic_proposals, collection_id, None)
         .map_err(|e| format!("Failed to index graphics: {}", e))?
         .0;
@@ -331,80 +750,154 @@ pub async fn try_register_collection(canister_id: Principal) -> Result<(), Strin
         indexed_graphics.clone(),
         collection_id,
         None,
+        metadata_path,
+        canister_id,
     )
     .map_err(|e| format!("Failed to index collection: {}", e))?;
-    store_state(indexed_graphics, indexed_collection)
+    let added: Vec<Change> = indexed_graphics
+        .iter()
+        .map(|g| Change::GraphicAdded { collection_id, ogid: g.ogid })
+        .collect();
+    store_state(indexed_graphics.clone(), indexed_collection.clone())
         .map_err(|e| format!("Failed to store state: {}", e))?;
-    Ok(())
+    changes::record(caller, [Change::CollectionRegistered { collection_id }].into_iter().chain(added));
+    icrc3::record_registration(caller, &indexed_collection, &indexed_graphics);
+    let now = time();
+    history::record_collection(collection_id, now, Some(&indexed_collection));
+    for graphic in &indexed_graphics {
+        history::record_graphic(graphic.ogid, now, Some(graphic));
+    }
+    Ok(collection_id)
 }
 
 /// The asynchronous function that implements the update process for an existing collection.
-/// It retrieves updated metadata and proposals, preserves the original registration timestamp,
+/// It retrieves updated metadata from the collection's configured metadata path, preserves the original registration timestamp,
 /// performs a differential update (both on the collection and on its graphics), and if any difference is detected,
 /// updates the persistent state atomically. If no changes are found, the update call fails.
-pub async fn try_update_collection(collection_id: u64, canister_id: Principal) -> Result<(), String> {
-    let metadata_blob = retrieve(canister_id, "/og_metadata.json")
-        .await
-        .map_err(|e| format!("Failed to retrieve metadata: {}", e))?;
-    let new_collection_proposal = parse_collection(&metadata_blob)
-        .map_err(|e| format!("Failed to parse collection: {}", e))?;
-    let new_graphic_proposals = parse_graphics(&metadata_blob)
-        .map_err(|e| format!("Failed to parse graphics: {}", e))?;
+pub async fn try_update_collection(
+    collection_id: u64,
+    canister_id: Principal,
+    caller: Principal,
+) -> Result<(), String> {
+    if apply_update(collection_id, canister_id, caller, true).await? {
+        Ok(())
+    } else {
+        Err("No differences detected. Update aborted.".to_string())
+    }
+}
+
+/// Applies the asset canister's current metadata to a registered collection, as `try_update_collection` does.
+/// Returns whether anything changed; an unchanged collection is left untouched.
+/// Also used by the automatic resync, for which "no changes" is not an error.
+/// Applied changes are recorded in the change feed on behalf of `caller`.
+/// Fails if `canister_id` is not the asset canister the collection was registered from.
+/// With `reinspect_assets` unset, graphics whose asset path is unchanged keep their recorded inspection and only
+/// new assets are fetched, so an update whose metadata is unchanged does not download any asset.
+/// With it set, every asset is fetched again; assets that cannot be read keep their recorded inspection,
+/// so a transient failure is not reported as a change.
+pub async fn apply_update(
+    collection_id: u64,
+    canister_id: Principal,
+    caller: Principal,
+    reinspect_assets: bool,
+) -> Result<bool, String> {
+    let collection = find_collection(collection_id).ok_or("Collection not registered".to_string())?;
+    collection.check_source_canister(canister_id)?;
+    let metadata_path = collection.metadata_path().to_string();
     
-    let old_collection = find_collection(collection_id)
-        .ok_or("Collection not registered".to_string())?;
+    let (new_collection_proposal, mut new_graphic_proposals) =
+        fetch_metadata_proposals(canister_id, &metadata_path).await?;
+    let recorded_graphics = collection_graphics(&collection);
+    if reinspect_assets {
+        media::inspect_graphics(canister_id, &mut new_graphic_proposals).await;
+        carry_over_inspection(&recorded_graphics, &mut new_graphic_proposals);
+    } else {
+        carry_over_inspection(&recorded_graphics, &mut new_graphic_proposals);
+        media::inspect_graphics(canister_id, &mut new_graphic_proposals).await;
+    }
     
-    // Check if the collection fields have changed.
-    let collection_changed = old_collection.title != new_collection_proposal.title
-        || old_collection.description != new_collection_proposal.description
-        || old_collection.artist != new_collection_proposal.artist
-        || old_collection.external_link != new_collection_proposal.external_link;
+    // Re-read the collection: it may have been changed or unregistered while the metadata was being fetched.
+    let old_collection = find_collection(collection_id)
+        .ok_or("Collection was unregistered during the update".to_string())?;
     
     // Get old graphics belonging to this collection.
-    let old_graphics = STATE.with(|state| {
-        state.borrow().registered_graphics
-            .iter()
-            .filter(|g| g.collection_id == collection_id)
-            .cloned()
-            .collect::<Vec<_>>()
-    });
+    let old_graphics = collection_graphics(&old_collection);
+    
+    // Compute what changed: collection fields, and graphics added, removed or modified.
+    let diff = diff::diff_collection(
+        &old_collection,
+        &old_graphics,
+        &new_collection_proposal,
+        &new_graphic_proposals,
+    );
+    
+    // If neither the collection fields nor any graphic changed, leave the collection untouched.
+    if diff.is_empty() {
+        return Ok(false);
+    }
+    let old_ogids: HashSet<u64> = old_graphics.iter().map(|g| g.ogid).collect();
     
     // Generate new graphics with differential update.
-    let (new_indexed_graphics, graphics_changed) = index_graphics_differential(
+    let (new_indexed_graphics, _) = index_graphics_differential(
         canister_id,
         new_graphic_proposals,
         collection_id,
         Some(old_graphics),
     )?;
     
-    // If neither the collection fields nor any graphic changed, abort the update.
-    if !collection_changed && !graphics_changed {
-        return Err("No differences detected. Update aborted.".to_string());
-    }
-    
     // Remove the old records for this collection.
-    STATE.with(|state| {
-        let mut s = state.borrow_mut();
-        s.registered_graphics.retain(|g| g.collection_id != collection_id);
-        s.registered_collections.retain(|c| c.collection_id != collection_id);
-    });
+    remove_collection_records(&old_collection);
     
-    // Build the updated collection record (preserving old registration timestamp).
-    let updated_collection = index_collection(
+    // Build the updated collection record (preserving old registration timestamp and sync settings).
+    let mut updated_collection = index_collection(
         new_collection_proposal,
         new_indexed_graphics.clone(),
         collection_id,
-        Some(old_collection.registration_timestamp),
+        Some(old_collection.registration_timestamp.clone()),
+        metadata_path,
+        canister_id,
     )
     .map_err(|e| format!("Failed to index collection: {}", e))?;
+    updated_collection.sync = old_collection.sync;
+    
+    // Describe the update for the change feed; added graphics are only known by OGID once indexed.
+    let mut events = Vec::new();
+    if !diff.collection_changes.is_empty() {
+        events.push(Change::CollectionModified { collection_id });
+    }
+    events.extend(
+        new_indexed_graphics
+            .iter()
+            .filter(|g| !old_ogids.contains(&g.ogid))
+            .map(|g| Change::GraphicAdded { collection_id, ogid: g.ogid }),
+    );
+    events.extend(diff.graphics_modified.iter().map(|g| Change::GraphicModified { collection_id, ogid: g.ogid }));
+    events.extend(diff.graphics_removed.iter().map(|g| Change::GraphicRemoved { collection_id, ogid: g.ogid }));
+    
+    // The ICRC-3 block pins the content of the graphics the update added or changed.
+    let modified_ogids: HashSet<u64> = diff.graphics_modified.iter().map(|g| g.ogid).collect();
+    let changed_graphics: Vec<IndexedGraphic> = new_indexed_graphics
+        .iter()
+        .filter(|g| !old_ogids.contains(&g.ogid) || modified_ogids.contains(&g.ogid))
+        .cloned()
+        .collect();
+    let removed_ogids: Vec<u64> = diff.graphics_removed.iter().map(|g| g.ogid).collect();
     
     // Atomically update the state.
-    STATE.with(|state| {
-        let mut s = state.borrow_mut();
-        s.registered_graphics.extend(new_indexed_graphics);
-        s.registered_collections.push(updated_collection);
-        Ok(())
-    })
+    store_state(new_indexed_graphics, updated_collection.clone())?;
+    changes::record(caller, events);
+    icrc3::record_update(caller, &updated_collection, &changed_graphics, &removed_ogids);
+    
+    // Keep the new versions; the versions they supersede stay in the history.
+    let now = time();
+    history::record_collection(collection_id, now, Some(&updated_collection));
+    for graphic in &changed_graphics {
+        history::record_graphic(graphic.ogid, now, Some(graphic));
+    }
+    for ogid in &removed_ogids {
+        history::record_graphic(*ogid, now, None);
+    }
+    Ok(true)
 }
 
 /// Temporary structure for a collection proposal, with a mandatory title.
@@ -414,6 +907,7 @@ pub struct CollectionProposal {
     pub description: Option<String>,
     pub artist: Option<String>,
     pub external_link: Option<String>,
+    pub tags: Vec<String>,
 }
 
 /// Temporary structure for a graphic proposal, with a mandatory title.
@@ -422,4 +916,12 @@ pub struct GraphicProposal {
     pub asset: String,
     pub title: String,
     pub description: Option<String>,
+    pub attributes: Vec<Attribute>,
+    pub tags: Vec<String>,
+    pub media_type: Option<String>,
+    // Filled in by `media::inspect_graphics` from the downloaded asset; None if it could not be read.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub byte_size: Option<u64>,
+    pub content_hash: Option<String>,
 }
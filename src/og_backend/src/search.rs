@@ -0,0 +1,358 @@
+// File: src/og_backend/src/search.rs
+
+use crate::frontend_api::{paginate, Page, PageRequest};
+use crate::registry::{IndexedCollection, IndexedGraphic};
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+/// Longest accepted search query, in bytes.
+pub const MAX_QUERY_LENGTH: usize = 256;
+/// Maximum number of distinct terms considered in a single query.
+pub const MAX_QUERY_TERMS: usize = 8;
+
+// Relative weight of a term depending on the field it was found in.
+const TITLE_WEIGHT: u32 = 3;
+const ARTIST_WEIGHT: u32 = 2;
+const DESCRIPTION_WEIGHT: u32 = 1;
+
+/// The kind of record a search hit refers to.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SearchKind {
+    Collection,
+    Graphic,
+}
+
+/// Optional restrictions applied to search results.
+/// `collection_id` limits hits to that collection and its graphics; `kind` limits hits to one record kind.
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct SearchFilters {
+    pub collection_id: Option<u64>,
+    pub kind: Option<SearchKind>,
+}
+
+/// A single ranked search result: a collection ID or an OGID, depending on `kind`.
+#[derive(CandidType, Serialize, Clone, Debug)]
+pub struct SearchHit {
+    pub kind: SearchKind,
+    pub id: u64,
+    pub collection_id: u64,
+    pub score: u32,
+}
+
+/// Identifies an indexed record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct DocKey {
+    kind: SearchKind,
+    id: u64,
+}
+
+/// Inverted index over the titles, descriptions and artists of registered records.
+/// It is derived data: kept on the heap and rebuilt from the registry after an upgrade.
+#[derive(Default)]
+struct SearchIndex {
+    /// token -> indexed record -> weight of the token within that record.
+    postings: BTreeMap<String, HashMap<DocKey, u32>>,
+    /// indexed record -> (owning collection_id, tokens it was indexed under), used for removal and filtering.
+    documents: HashMap<DocKey, (u64, Vec<String>)>,
+}
+
+thread_local! {
+    static SEARCH_INDEX: RefCell<SearchIndex> = RefCell::new(SearchIndex::default());
+}
+
+/// Splits text into lowercase alphanumeric tokens.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+}
+
+impl SearchIndex {
+    fn insert(&mut self, doc: DocKey, collection_id: u64, fields: &[(Option<&str>, u32)]) {
+        self.remove(doc);
+        let mut weights: HashMap<String, u32> = HashMap::new();
+        for (text, weight) in fields {
+            for token in text.iter().flat_map(|t| tokenize(t)) {
+                *weights.entry(token).or_default() += weight;
+            }
+        }
+        let tokens = weights.keys().cloned().collect();
+        for (token, weight) in weights {
+            self.postings.entry(token).or_default().insert(doc, weight);
+        }
+        self.documents.insert(doc, (collection_id, tokens));
+    }
+
+    fn remove(&mut self, doc: DocKey) {
+        if let Some((_, tokens)) = self.documents.remove(&doc) {
+            for token in tokens {
+                if let Some(postings) = self.postings.get_mut(&token) {
+                    postings.remove(&doc);
+                    if postings.is_empty() {
+                        self.postings.remove(&token);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Scores every record matching all query terms. A term matches any indexed token it is a prefix of;
+    /// exact matches count double.
+    fn score(&self, terms: &[String]) -> HashMap<DocKey, u32> {
+        let mut scores: Option<HashMap<DocKey, u32>> = None;
+        for term in terms {
+            let mut term_scores: HashMap<DocKey, u32> = HashMap::new();
+            for (token, postings) in self
+                .postings
+                .range(term.clone()..)
+                .take_while(|(token, _)| token.starts_with(term.as_str()))
+            {
+                let boost = if token == term { 2 } else { 1 };
+                for (doc, weight) in postings {
+                    let score = term_scores.entry(*doc).or_default();
+                    *score = (*score).max(weight * boost);
+                }
+            }
+            scores = Some(match scores {
+                None => term_scores,
+                Some(previous) => previous
+                    .into_iter()
+                    .filter_map(|(doc, score)| term_scores.get(&doc).map(|s| (doc, score + s)))
+                    .collect(),
+            });
+        }
+        scores.unwrap_or_default()
+    }
+}
+
+/// Indexes (or re-indexes) a collection and its graphics.
+/// Graphics are also indexed under their collection's artist.
+pub fn index_records(collection: &IndexedCollection, graphics: &[IndexedGraphic]) {
+    SEARCH_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        let artist = collection.artist.as_deref();
+        index.insert(
+            DocKey { kind: SearchKind::Collection, id: collection.collection_id },
+            collection.collection_id,
+            &[
+                (Some(&collection.title), TITLE_WEIGHT),
+                (artist, ARTIST_WEIGHT),
+                (collection.description.as_deref(), DESCRIPTION_WEIGHT),
+            ],
+        );
+        for graphic in graphics {
+            index.insert(
+                DocKey { kind: SearchKind::Graphic, id: graphic.ogid },
+                collection.collection_id,
+                &[
+                    (Some(&graphic.title), TITLE_WEIGHT),
+                    (artist, ARTIST_WEIGHT),
+                    (graphic.description.as_deref(), DESCRIPTION_WEIGHT),
+                ],
+            );
+        }
+    });
+}
+
+/// Removes a collection and the given graphics from the index.
+pub fn remove_records(collection_id: u64, ogids: &[u64]) {
+    SEARCH_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        index.remove(DocKey { kind: SearchKind::Collection, id: collection_id });
+        for ogid in ogids {
+            index.remove(DocKey { kind: SearchKind::Graphic, id: *ogid });
+        }
+    });
+}
+
+/// Empties the index, ahead of a rebuild.
+pub fn clear() {
+    SEARCH_INDEX.with(|index| {
+        *index.borrow_mut() = SearchIndex::default();
+    });
+}
+
+/// Returns one page of records matching every term of `query`, ranked by score.
+pub fn try_search(
+    query: &str,
+    filters: &SearchFilters,
+    page: &PageRequest,
+) -> Result<Page<SearchHit>, String> {
+    if query.len() > MAX_QUERY_LENGTH {
+        return Err(format!("Query too long (maximum is {} bytes)", MAX_QUERY_LENGTH));
+    }
+    let mut terms: Vec<String> = tokenize(query).collect();
+    terms.sort();
+    terms.dedup();
+    if terms.is_empty() {
+        return Err("Query contains no searchable terms".to_string());
+    }
+    if terms.len() > MAX_QUERY_TERMS {
+        return Err(format!("Too many query terms (maximum is {})", MAX_QUERY_TERMS));
+    }
+
+    let mut hits: Vec<SearchHit> = SEARCH_INDEX.with(|index| {
+        let index = index.borrow();
+        index
+            .score(&terms)
+            .into_iter()
+            .filter_map(|(doc, score)| {
+                let collection_id = index.documents.get(&doc)?.0;
+                Some(SearchHit { kind: doc.kind, id: doc.id, collection_id, score })
+            })
+            .filter(|hit| filters.kind.is_none_or(|kind| kind == hit.kind))
+            .filter(|hit| filters.collection_id.is_none_or(|id| id == hit.collection_id))
+            .collect()
+    });
+    // Highest score first; collections before graphics, then by ID, for a stable order across pages.
+    hits.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then(a.kind.cmp(&b.kind))
+            .then(a.id.cmp(&b.id))
+    });
+    let total = hits.len() as u64;
+    Ok(paginate(hits.into_iter(), total, page))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Indexes a record directly, as `index_records` does for registered collections and graphics.
+    fn index(kind: SearchKind, id: u64, collection_id: u64, title: &str, artist: Option<&str>, description: Option<&str>) {
+        SEARCH_INDEX.with(|index| {
+            index.borrow_mut().insert(
+                DocKey { kind, id },
+                collection_id,
+                &[
+                    (Some(title), TITLE_WEIGHT),
+                    (artist, ARTIST_WEIGHT),
+                    (description, DESCRIPTION_WEIGHT),
+                ],
+            );
+        });
+    }
+
+    fn search(query: &str, filters: &SearchFilters) -> Result<Vec<(SearchKind, u64, u32)>, String> {
+        let page = try_search(query, filters, &PageRequest { cursor: None, limit: None })?;
+        Ok(page.items.into_iter().map(|hit| (hit.kind, hit.id, hit.score)).collect())
+    }
+
+    fn ids(query: &str) -> Vec<u64> {
+        search(query, &SearchFilters::default())
+            .unwrap()
+            .into_iter()
+            .map(|(_, id, _)| id)
+            .collect()
+    }
+
+    #[test]
+    fn tokenizes_on_non_alphanumeric_characters() {
+        let tokens: Vec<String> = tokenize("  Blue-Moon, NIGHT_sky #3 ").collect();
+        assert_eq!(tokens, ["blue", "moon", "night", "sky", "3"]);
+        let tokens: Vec<String> = tokenize("Café Ünïcode").collect();
+        assert_eq!(tokens, ["café", "ünïcode"]);
+        assert_eq!(tokenize(" -- ").count(), 0);
+    }
+
+    #[test]
+    fn rejects_queries_without_terms() {
+        clear();
+        let expected = Err("Query contains no searchable terms".to_string());
+        assert_eq!(search("", &SearchFilters::default()), expected);
+        assert_eq!(search(" ?! ", &SearchFilters::default()), expected);
+    }
+
+    #[test]
+    fn rejects_overlong_queries() {
+        clear();
+        let long = "a".repeat(MAX_QUERY_LENGTH + 1);
+        assert!(search(&long, &SearchFilters::default()).unwrap_err().contains("Query too long"));
+        let many = (0..=MAX_QUERY_TERMS).map(|i| format!("t{}", i)).collect::<Vec<_>>().join(" ");
+        assert!(search(&many, &SearchFilters::default()).unwrap_err().contains("Too many query terms"));
+        // Repeated terms count once.
+        assert_eq!(search(&"x ".repeat(MAX_QUERY_TERMS + 1), &SearchFilters::default()), Ok(vec![]));
+    }
+
+    #[test]
+    fn ranks_by_field_weight() {
+        clear();
+        index(SearchKind::Graphic, 1, 10, "Untitled", None, Some("A moon"));
+        index(SearchKind::Graphic, 2, 10, "Untitled", Some("Moon"), None);
+        index(SearchKind::Graphic, 3, 10, "Moon", None, None);
+        assert_eq!(
+            search("moon", &SearchFilters::default()).unwrap(),
+            [
+                (SearchKind::Graphic, 3, TITLE_WEIGHT * 2),
+                (SearchKind::Graphic, 2, ARTIST_WEIGHT * 2),
+                (SearchKind::Graphic, 1, DESCRIPTION_WEIGHT * 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn ranks_exact_matches_above_prefix_matches() {
+        clear();
+        index(SearchKind::Graphic, 1, 10, "Moonlight", None, None);
+        index(SearchKind::Graphic, 2, 10, "Moon", None, None);
+        assert_eq!(ids("moon"), [2, 1]);
+        assert_eq!(ids("moonl"), [1]);
+    }
+
+    #[test]
+    fn requires_every_term_and_sums_their_scores() {
+        clear();
+        index(SearchKind::Graphic, 1, 10, "Blue moon", None, None);
+        index(SearchKind::Graphic, 2, 10, "Blue sky", None, None);
+        assert_eq!(ids("blue"), [1, 2]);
+        assert_eq!(
+            search("MOON blue", &SearchFilters::default()).unwrap(),
+            [(SearchKind::Graphic, 1, TITLE_WEIGHT * 2 * 2)]
+        );
+        assert_eq!(ids("blue night"), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn breaks_ties_by_kind_then_id() {
+        clear();
+        index(SearchKind::Graphic, 5, 20, "Sun", None, None);
+        index(SearchKind::Graphic, 4, 20, "Sun", None, None);
+        index(SearchKind::Collection, 20, 20, "Sun", None, None);
+        assert_eq!(
+            search("sun", &SearchFilters::default()).unwrap(),
+            [
+                (SearchKind::Collection, 20, TITLE_WEIGHT * 2),
+                (SearchKind::Graphic, 4, TITLE_WEIGHT * 2),
+                (SearchKind::Graphic, 5, TITLE_WEIGHT * 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_filters() {
+        clear();
+        index(SearchKind::Collection, 10, 10, "Star", None, None);
+        index(SearchKind::Graphic, 1, 10, "Star", None, None);
+        index(SearchKind::Graphic, 2, 20, "Star", None, None);
+        let in_collection = SearchFilters { collection_id: Some(10), kind: None };
+        assert_eq!(
+            search("star", &in_collection).unwrap(),
+            [(SearchKind::Collection, 10, 6), (SearchKind::Graphic, 1, 6)]
+        );
+        let graphics = SearchFilters { collection_id: None, kind: Some(SearchKind::Graphic) };
+        assert_eq!(search("star", &graphics).unwrap(), [(SearchKind::Graphic, 1, 6), (SearchKind::Graphic, 2, 6)]);
+    }
+
+    #[test]
+    fn forgets_removed_records() {
+        clear();
+        index(SearchKind::Collection, 10, 10, "Star", None, None);
+        index(SearchKind::Graphic, 1, 10, "Star", None, None);
+        remove_records(10, &[1]);
+        assert_eq!(ids("star"), Vec::<u64>::new());
+        SEARCH_INDEX.with(|index| assert!(index.borrow().postings.is_empty()));
+    }
+}
@@ -0,0 +1,184 @@
+// File: src/og_backend/src/sync.rs
+
+use crate::registry::{self, find_collection, get_current_timestamp, IndexedCollection};
+use candid::CandidType;
+use ic_cdk::api::time;
+use ic_cdk::spawn;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+/// How often the scheduler looks for collections that are due for a resync.
+const SYNC_TICK_INTERVAL: Duration = Duration::from_secs(60);
+/// Shortest sync interval a collection can be configured with.
+pub const MIN_SYNC_INTERVAL_SECONDS: u64 = 10 * 60;
+/// Upper bound on the delay between attempts once a collection keeps failing to sync.
+const MAX_BACKOFF_SECONDS: u64 = 7 * 24 * 60 * 60;
+/// Maximum number of collections resynced at the same time.
+const MAX_CONCURRENT_SYNCS: usize = 4;
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// The result of the last automatic resync of a collection.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum SyncOutcome {
+    Updated,
+    Unchanged,
+    Failed { reason: String },
+}
+
+/// Automatic resync settings and status of a collection, persisted on its record.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SyncState {
+    pub interval_seconds: u64,
+    /// When the next resync is due, in nanoseconds since the epoch.
+    pub next_sync_at: u64,
+    pub last_sync_timestamp: Option<String>,
+    pub last_outcome: Option<SyncOutcome>,
+    /// Number of failed resyncs in a row; each one doubles the delay before the next attempt.
+    pub consecutive_failures: u32,
+}
+
+impl SyncState {
+    /// Returns the delay before the next resync, doubling the interval for every consecutive failure
+    /// up to `MAX_BACKOFF_SECONDS` (or the interval itself, if that is longer).
+    fn next_delay_seconds(&self) -> u64 {
+        let backoff = 1u64 << self.consecutive_failures.min(16);
+        self.interval_seconds
+            .saturating_mul(backoff)
+            .min(MAX_BACKOFF_SECONDS.max(self.interval_seconds))
+    }
+}
+
+thread_local! {
+    /// collection_id -> when its next resync is due. Derived from the collection records and
+    /// rebuilt after an upgrade, like the other in-memory indexes.
+    static SCHEDULE: RefCell<BTreeMap<u64, u64>> = const { RefCell::new(BTreeMap::new()) };
+    /// Collections whose resync is currently awaiting the asset canister.
+    static IN_FLIGHT: RefCell<BTreeSet<u64>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+/// Schedules (or unschedules) a collection according to its sync settings.
+pub fn schedule_collection(collection: &IndexedCollection) {
+    SCHEDULE.with(|schedule| {
+        let mut schedule = schedule.borrow_mut();
+        match &collection.sync {
+            Some(sync) => schedule.insert(collection.collection_id, sync.next_sync_at),
+            None => schedule.remove(&collection.collection_id),
+        };
+    });
+}
+
+/// Removes a collection from the schedule.
+pub fn unschedule_collection(collection_id: u64) {
+    SCHEDULE.with(|schedule| {
+        schedule.borrow_mut().remove(&collection_id);
+    });
+}
+
+/// Empties the schedule, ahead of a rebuild.
+pub fn clear_schedule() {
+    SCHEDULE.with(|schedule| schedule.borrow_mut().clear());
+}
+
+/// Starts the timer that resyncs due collections. Timers do not survive upgrades,
+/// so this is called from both `init` and `post_upgrade`.
+pub fn start_sync_timer() {
+    ic_cdk_timers::set_timer_interval(SYNC_TICK_INTERVAL, run_due_syncs);
+}
+
+/// Spawns a resync for every due collection, keeping at most `MAX_CONCURRENT_SYNCS` in flight.
+fn run_due_syncs() {
+    let now = time();
+    let due: Vec<u64> = IN_FLIGHT.with(|in_flight| {
+        let mut in_flight = in_flight.borrow_mut();
+        let available = MAX_CONCURRENT_SYNCS.saturating_sub(in_flight.len());
+        let due: Vec<u64> = SCHEDULE.with(|schedule| {
+            schedule
+                .borrow()
+                .iter()
+                .filter(|(id, next_sync_at)| **next_sync_at <= now && !in_flight.contains(*id))
+                .map(|(id, _)| *id)
+                .take(available)
+                .collect()
+        });
+        in_flight.extend(due.iter().copied());
+        due
+    });
+    for collection_id in due {
+        spawn(sync_collection(collection_id));
+    }
+}
+
+/// Removes a collection from `IN_FLIGHT` when dropped. The CDK drops pending futures when a callback traps,
+/// so the collection is released even if its resync never completes.
+struct InFlightGuard(u64);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        IN_FLIGHT.with(|in_flight| in_flight.borrow_mut().remove(&self.0));
+    }
+}
+
+/// Runs the `update_collection` logic for one collection and records the outcome on its sync status.
+/// Assets of unchanged graphics are not fetched again, so a resync whose metadata is unchanged only
+/// downloads the metadata file.
+async fn sync_collection(collection_id: u64) {
+    let guard = InFlightGuard(collection_id);
+    let result = match find_collection(collection_id).map(|c| c.source_canister()) {
+        None => Err("Collection not registered".to_string()),
+        Some(None) => Err("The collection's asset canister is unknown".to_string()),
+        Some(Some(canister_id)) => {
+            registry::apply_update(collection_id, canister_id, ic_cdk::api::id(), false).await
+        }
+    };
+    drop(guard);
+
+    // The collection may have been unregistered, or its sync disabled, in the meantime.
+    let Some(mut sync) = find_collection(collection_id).and_then(|c| c.sync) else {
+        return;
+    };
+    match result {
+        Ok(changed) => {
+            sync.consecutive_failures = 0;
+            sync.last_outcome = Some(if changed { SyncOutcome::Updated } else { SyncOutcome::Unchanged });
+        }
+        Err(reason) => {
+            ic_cdk::println!("Sync of collection {} failed: {}", collection_id, reason);
+            sync.consecutive_failures = sync.consecutive_failures.saturating_add(1);
+            sync.last_outcome = Some(SyncOutcome::Failed { reason });
+        }
+    }
+    sync.last_sync_timestamp = Some(get_current_timestamp());
+    sync.next_sync_at = time().saturating_add(sync.next_delay_seconds().saturating_mul(NANOS_PER_SECOND));
+    if let Err(err) = registry::set_collection_sync(collection_id, Some(sync)) {
+        ic_cdk::println!("Failed to record sync of collection {}: {}", collection_id, err);
+    }
+}
+
+/// Enables, changes or (with `None`) disables the automatic resync of a collection.
+/// The first resync runs one interval from now; the status of previous resyncs is kept.
+pub fn try_set_sync_interval(collection_id: u64, interval_seconds: Option<u64>) -> Result<(), String> {
+    let collection = find_collection(collection_id).ok_or("Collection not found".to_string())?;
+    let sync = match interval_seconds {
+        None => None,
+        Some(interval) if interval < MIN_SYNC_INTERVAL_SECONDS => {
+            return Err(format!(
+                "Sync interval too short (minimum is {} seconds)",
+                MIN_SYNC_INTERVAL_SECONDS
+            ))
+        }
+        Some(interval) => {
+            let previous = collection.sync;
+            Some(SyncState {
+                interval_seconds: interval,
+                next_sync_at: time().saturating_add(interval.saturating_mul(NANOS_PER_SECOND)),
+                last_sync_timestamp: previous.as_ref().and_then(|s| s.last_sync_timestamp.clone()),
+                last_outcome: previous.as_ref().and_then(|s| s.last_outcome.clone()),
+                consecutive_failures: 0,
+            })
+        }
+    };
+    registry::set_collection_sync(collection_id, sync)
+}
@@ -0,0 +1,193 @@
+// File: src/og_backend/src/tags.rs
+
+use crate::frontend_api::{paginate, Page, PageRequest};
+use crate::registry::{IndexedCollection, IndexedGraphic};
+use crate::search::SearchKind;
+use candid::CandidType;
+use serde::Serialize;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+/// Maximum number of tags accepted on a single collection or graphic.
+pub const MAX_TAGS: usize = 32;
+/// Longest accepted tag, in characters.
+pub const MAX_TAG_LENGTH: usize = 64;
+
+/// A collection or graphic carrying a given tag: a collection ID or an OGID, depending on `kind`.
+#[derive(CandidType, Serialize, Clone, Debug)]
+pub struct TaggedRecord {
+    pub kind: SearchKind,
+    pub id: u64,
+    pub collection_id: u64,
+}
+
+/// A tag in use, with the number of collections and graphics carrying it.
+#[derive(CandidType, Serialize, Clone, Debug)]
+pub struct TagCount {
+    pub tag: String,
+    pub collection_count: u64,
+    pub graphic_count: u64,
+}
+
+/// Tag -> tagged records. Like the search index, it is derived data:
+/// kept on the heap and rebuilt from the registry after an upgrade.
+#[derive(Default)]
+struct TagIndex {
+    /// tag -> (kind, id) -> owning collection_id, ordered so that collections come before graphics.
+    tags: BTreeMap<String, BTreeMap<(SearchKind, u64), u64>>,
+    /// (kind, id) -> tags the record was indexed under, used for removal.
+    records: HashMap<(SearchKind, u64), Vec<String>>,
+}
+
+thread_local! {
+    static TAG_INDEX: RefCell<TagIndex> = RefCell::new(TagIndex::default());
+}
+
+/// Normalizes a tag for indexing and lookup: trimmed, lowercase, with inner whitespace collapsed,
+/// so that "Mixed  Media" and "mixed media" are the same tag.
+pub fn normalize_tag(tag: &str) -> String {
+    tag.split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Normalizes a single tag from the metadata, rejecting empty and overly long tags.
+pub fn parse_tag(tag: &Value) -> Result<String, String> {
+    let tag = tag.as_str().ok_or("Tag must be a string")?;
+    let normalized = normalize_tag(tag);
+    if normalized.is_empty() {
+        return Err("Tag must not be empty".to_string());
+    }
+    if normalized.chars().count() > MAX_TAG_LENGTH {
+        return Err(format!("Tag '{}' is too long (maximum is {} characters)", tag, MAX_TAG_LENGTH));
+    }
+    Ok(normalized)
+}
+
+/// Parses the optional `tags` array of a collection or graphic.
+/// Tags are normalized and deduplicated, keeping their first occurrence order.
+pub fn parse_tags(object: &Value) -> Result<Vec<String>, String> {
+    let entries = match object.get("tags") {
+        None => return Ok(Vec::new()),
+        Some(Value::Array(entries)) => entries,
+        Some(_) => return Err("'tags' is not an array".to_string()),
+    };
+    if entries.len() > MAX_TAGS {
+        return Err(format!("Too many tags (maximum is {})", MAX_TAGS));
+    }
+    let mut tags: Vec<String> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let tag = parse_tag(entry)?;
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    Ok(tags)
+}
+
+impl TagIndex {
+    fn insert(&mut self, tags: &[String], key: (SearchKind, u64), collection_id: u64) {
+        self.remove(key);
+        if tags.is_empty() {
+            return;
+        }
+        for tag in tags {
+            self.tags.entry(tag.clone()).or_default().insert(key, collection_id);
+        }
+        self.records.insert(key, tags.to_vec());
+    }
+
+    fn remove(&mut self, key: (SearchKind, u64)) {
+        if let Some(tags) = self.records.remove(&key) {
+            for tag in tags {
+                if let Some(records) = self.tags.get_mut(&tag) {
+                    records.remove(&key);
+                    if records.is_empty() {
+                        self.tags.remove(&tag);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Indexes (or re-indexes) the tags of a collection and its graphics.
+pub fn index_records(collection: &IndexedCollection, graphics: &[IndexedGraphic]) {
+    TAG_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        index.insert(
+            collection.tags(),
+            (SearchKind::Collection, collection.collection_id),
+            collection.collection_id,
+        );
+        for graphic in graphics {
+            index.insert(graphic.tags(), (SearchKind::Graphic, graphic.ogid), collection.collection_id);
+        }
+    });
+}
+
+/// Removes a collection and the given graphics from the tag index.
+pub fn remove_records(collection_id: u64, ogids: &[u64]) {
+    TAG_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        index.remove((SearchKind::Collection, collection_id));
+        for ogid in ogids {
+            index.remove((SearchKind::Graphic, *ogid));
+        }
+    });
+}
+
+/// Empties the tag index, ahead of a rebuild.
+pub fn clear() {
+    TAG_INDEX.with(|index| {
+        *index.borrow_mut() = TagIndex::default();
+    });
+}
+
+/// Returns every tag in use, in ascending order, with the number of collections and graphics carrying it.
+pub fn tag_counts() -> Vec<TagCount> {
+    TAG_INDEX.with(|index| {
+        index
+            .borrow()
+            .tags
+            .iter()
+            .map(|(tag, records)| {
+                let collection_count = records
+                    .keys()
+                    .take_while(|(kind, _)| *kind == SearchKind::Collection)
+                    .count() as u64;
+                TagCount {
+                    tag: tag.clone(),
+                    collection_count,
+                    graphic_count: records.len() as u64 - collection_count,
+                }
+            })
+            .collect()
+    })
+}
+
+/// Returns one page of the collections and graphics carrying `tag`: collections first, then graphics,
+/// each in ascending ID order. The tag is normalized before lookup.
+pub fn try_fetch_by_tag(tag: &str, page: &PageRequest) -> Result<Page<TaggedRecord>, String> {
+    let tag = normalize_tag(tag);
+    if tag.is_empty() {
+        return Err("Tag must not be empty".to_string());
+    }
+    Ok(TAG_INDEX.with(|index| {
+        let index = index.borrow();
+        match index.tags.get(&tag) {
+            Some(records) => paginate(
+                records.iter().map(|(&(kind, id), &collection_id)| TaggedRecord {
+                    kind,
+                    id,
+                    collection_id,
+                }),
+                records.len() as u64,
+                page,
+            ),
+            None => paginate(std::iter::empty(), 0, page),
+        }
+    }))
+}
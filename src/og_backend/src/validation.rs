@@ -0,0 +1,464 @@
+// File: src/og_backend/src/validation.rs
+
+use crate::attributes::{parse_attribute, MAX_ATTRIBUTES_PER_GRAPHIC};
+use crate::media::is_valid_media_type;
+use crate::registry::{parse_collection, parse_graphics, resolve_metadata_path, retrieve};
+use crate::tags::{parse_tag, MAX_TAGS};
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+
+/// A single problem found in a metadata file, located by its JSON path (e.g. `$.graphics[2].title`).
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ValidationIssue {
+    pub path: String,
+    pub message: String,
+}
+
+/// The outcome of validating a metadata file without registering it.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ValidationReport {
+    pub valid: bool,
+    pub graphic_count: u64,
+    pub issues: Vec<ValidationIssue>,
+}
+
+/// Collects validation issues as the metadata document is walked.
+#[derive(Default)]
+struct Issues(Vec<ValidationIssue>);
+
+impl Issues {
+    fn push(&mut self, path: &str, message: impl Into<String>) {
+        self.0.push(ValidationIssue {
+            path: path.to_string(),
+            message: message.into(),
+        });
+    }
+
+    /// Checks that `object[key]` is present and holds a non-empty string.
+    fn required_string(&mut self, object: &Map<String, Value>, path: &str, key: &str) -> Option<String> {
+        let field_path = format!("{}.{}", path, key);
+        match object.get(key) {
+            None => {
+                self.push(&field_path, format!("Missing required '{}' field", key));
+                None
+            }
+            Some(Value::String(s)) if s.trim().is_empty() => {
+                self.push(&field_path, format!("'{}' must not be empty", key));
+                None
+            }
+            Some(Value::String(s)) => Some(s.clone()),
+            Some(other) => {
+                self.push(&field_path, format!("'{}' must be a string, found {}", key, type_name(other)));
+                None
+            }
+        }
+    }
+
+    /// Checks that `object[key]`, if present, holds a string.
+    fn optional_string(&mut self, object: &Map<String, Value>, path: &str, key: &str) {
+        match object.get(key) {
+            None | Some(Value::String(_)) => {}
+            Some(other) => self.push(
+                &format!("{}.{}", path, key),
+                format!("'{}' must be a string, found {}", key, type_name(other)),
+            ),
+        }
+    }
+
+    /// Checks every entry of the optional `object.tags` array.
+    fn tags(&mut self, object: &Map<String, Value>, path: &str) {
+        let tags_path = format!("{}.tags", path);
+        let entries = match object.get("tags") {
+            None => return,
+            Some(Value::Array(entries)) => entries,
+            Some(other) => {
+                self.push(&tags_path, format!("'tags' must be an array, found {}", type_name(other)));
+                return;
+            }
+        };
+        if entries.len() > MAX_TAGS {
+            self.push(&tags_path, format!("Too many tags (maximum is {})", MAX_TAGS));
+        }
+        for (i, entry) in entries.iter().enumerate() {
+            if let Err(e) = parse_tag(entry) {
+                self.push(&format!("{}[{}]", tags_path, i), e);
+            }
+        }
+    }
+
+    /// Checks every entry of the optional `object.attributes` array, reporting each invalid or duplicate entry.
+    fn attributes(&mut self, object: &Map<String, Value>, path: &str) {
+        let attributes_path = format!("{}.attributes", path);
+        let entries = match object.get("attributes") {
+            None => return,
+            Some(Value::Array(entries)) => entries,
+            Some(other) => {
+                self.push(
+                    &attributes_path,
+                    format!("'attributes' must be an array, found {}", type_name(other)),
+                );
+                return;
+            }
+        };
+        if entries.len() > MAX_ATTRIBUTES_PER_GRAPHIC {
+            self.push(
+                &attributes_path,
+                format!("Too many attributes (maximum is {})", MAX_ATTRIBUTES_PER_GRAPHIC),
+            );
+        }
+        let mut keys = HashSet::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let entry_path = format!("{}[{}]", attributes_path, i);
+            match parse_attribute(entry) {
+                Ok(attribute) if !keys.insert(attribute.key.clone()) => self.push(
+                    &format!("{}.key", entry_path),
+                    format!("Duplicate attribute key '{}'", attribute.key),
+                ),
+                Ok(_) => {}
+                Err(e) => self.push(&entry_path, e),
+            }
+        }
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// Walks the whole metadata document and returns every problem found, rather than stopping at the first one.
+/// Returns the issues together with the number of graphic entries in the document.
+pub fn validate_metadata(metadata_blob: &str) -> (Vec<ValidationIssue>, u64) {
+    let mut issues = Issues::default();
+    let root: Value = match serde_json::from_str(metadata_blob) {
+        Ok(v) => v,
+        Err(e) => {
+            issues.push("$", format!("JSON parse error: {}", e));
+            return (issues.0, 0);
+        }
+    };
+    let Some(root) = root.as_object() else {
+        issues.push("$", format!("Metadata must be an object, found {}", type_name(&root)));
+        return (issues.0, 0);
+    };
+
+    match root.get("collection") {
+        None => issues.push("$.collection", "Missing 'collection' field"),
+        Some(Value::Object(collection)) => {
+            issues.required_string(collection, "$.collection", "title");
+            for key in ["description", "artist", "external_link"] {
+                issues.optional_string(collection, "$.collection", key);
+            }
+            issues.tags(collection, "$.collection");
+        }
+        Some(other) => issues.push(
+            "$.collection",
+            format!("'collection' must be an object, found {}", type_name(other)),
+        ),
+    }
+
+    let mut graphic_count = 0;
+    match root.get("graphics") {
+        None => issues.push("$.graphics", "Missing 'graphics' field"),
+        Some(Value::Array(graphics)) => {
+            graphic_count = graphics.len() as u64;
+            // asset path -> JSON path of its first occurrence
+            let mut seen_assets: HashMap<String, String> = HashMap::new();
+            for (i, graphic) in graphics.iter().enumerate() {
+                let path = format!("$.graphics[{}]", i);
+                let Some(graphic) = graphic.as_object() else {
+                    issues.push(&path, format!("Graphic must be an object, found {}", type_name(graphic)));
+                    continue;
+                };
+                if let Some(asset) = issues.required_string(graphic, &path, "asset") {
+                    let asset_path = format!("{}.asset", path);
+                    if let Some(first) = seen_assets.get(&asset) {
+                        issues.push(
+                            &asset_path,
+                            format!("Duplicate asset path '{}' (first used at {})", asset, first),
+                        );
+                    } else {
+                        seen_assets.insert(asset, asset_path);
+                    }
+                }
+                issues.required_string(graphic, &path, "title");
+                issues.optional_string(graphic, &path, "description");
+                if let Some(Value::String(media_type)) = graphic.get("media_type") {
+                    if !is_valid_media_type(media_type) {
+                        issues.push(
+                            &format!("{}.media_type", path),
+                            format!("'{}' is not a valid media type (expected e.g. 'image/webp')", media_type),
+                        );
+                    }
+                } else {
+                    issues.optional_string(graphic, &path, "media_type");
+                }
+                issues.attributes(graphic, &path);
+                issues.tags(graphic, &path);
+            }
+        }
+        Some(other) => issues.push(
+            "$.graphics",
+            format!("'graphics' must be an array, found {}", type_name(other)),
+        ),
+    }
+
+    (issues.0, graphic_count)
+}
+
+/// Rejects a metadata document that `validate_metadata` finds any issue in, listing every issue in the error.
+/// Registration and updates run this before parsing, so they accept exactly what a dry run reports as valid.
+pub fn check_metadata(metadata_blob: &str) -> Result<(), String> {
+    let (issues, _) = validate_metadata(metadata_blob);
+    if issues.is_empty() {
+        return Ok(());
+    }
+    let issues: Vec<String> = issues
+        .iter()
+        .map(|issue| format!("{}: {}", issue.path, issue.message))
+        .collect();
+    Err(format!("Invalid metadata: {}", issues.join("; ")))
+}
+
+/// Retrieves the metadata of the given asset canister and validates it without touching the registry.
+/// The registration parsers are run as well, so that anything they would reject is reported too.
+pub async fn try_validate_collection(canister_id: Principal, metadata_path: Option<String>) -> ValidationReport {
+    let failed = |message: String| ValidationReport {
+        valid: false,
+        graphic_count: 0,
+        issues: vec![ValidationIssue {
+            path: "$".to_string(),
+            message,
+        }],
+    };
+    let metadata_path = match resolve_metadata_path(metadata_path) {
+        Ok(path) => path,
+        Err(e) => return failed(e),
+    };
+    let metadata_blob = match retrieve(canister_id, &metadata_path).await {
+        Ok(blob) => blob,
+        Err(e) => return failed(format!("Failed to retrieve metadata: {}", e)),
+    };
+
+    let (mut issues, graphic_count) = validate_metadata(&metadata_blob);
+    if issues.is_empty() {
+        if let Err(e) = parse_collection(&metadata_blob) {
+            issues.push(ValidationIssue {
+                path: "$.collection".to_string(),
+                message: format!("Failed to parse collection: {}", e),
+            });
+        }
+        if let Err(e) = parse_graphics(&metadata_blob) {
+            issues.push(ValidationIssue {
+                path: "$.graphics".to_string(),
+                message: format!("Failed to parse graphics: {}", e),
+            });
+        }
+    }
+    ValidationReport {
+        valid: issues.is_empty(),
+        graphic_count,
+        issues,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn issues_of(metadata: Value) -> Vec<ValidationIssue> {
+        validate_metadata(&metadata.to_string()).0
+    }
+
+    /// Validates a document with a valid collection and the given graphics.
+    fn graphic_issues(graphics: Value) -> Vec<ValidationIssue> {
+        issues_of(json!({ "collection": { "title": "Collection" }, "graphics": graphics }))
+    }
+
+    fn assert_single_issue(issues: &[ValidationIssue], path: &str, message: &str) {
+        assert_eq!(issues.len(), 1, "expected a single issue, got {:?}", issues);
+        assert_eq!(issues[0].path, path);
+        assert!(
+            issues[0].message.contains(message),
+            "'{}' does not contain '{}'",
+            issues[0].message,
+            message
+        );
+    }
+
+    #[test]
+    fn accepts_valid_metadata() {
+        let (issues, graphic_count) = validate_metadata(
+            &json!({
+                "collection": { "title": "Collection", "description": "A collection", "tags": ["Art"] },
+                "graphics": [
+                    { "asset": "/1.png", "title": "One", "media_type": "image/png", "tags": ["red"] },
+                    { "asset": "/2.png", "title": "Two", "attributes": [{ "key": "Size", "value": 2 }] }
+                ]
+            })
+            .to_string(),
+        );
+        assert!(issues.is_empty(), "{:?}", issues);
+        assert_eq!(graphic_count, 2);
+        assert!(check_metadata(&json!({ "collection": { "title": "C" }, "graphics": [] }).to_string()).is_ok());
+    }
+
+    #[test]
+    fn reports_unparsable_json() {
+        let (issues, graphic_count) = validate_metadata("{ not json");
+        assert_single_issue(&issues, "$", "JSON parse error");
+        assert_eq!(graphic_count, 0);
+    }
+
+    #[test]
+    fn reports_a_non_object_document() {
+        assert_single_issue(&issues_of(json!([])), "$", "Metadata must be an object, found an array");
+    }
+
+    #[test]
+    fn reports_missing_and_mistyped_sections() {
+        let issues = issues_of(json!({}));
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].path, "$.collection");
+        assert_eq!(issues[0].message, "Missing 'collection' field");
+        assert_eq!(issues[1].path, "$.graphics");
+        assert_eq!(issues[1].message, "Missing 'graphics' field");
+
+        let issues = issues_of(json!({ "collection": "C", "graphics": {} }));
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].message, "'collection' must be an object, found a string");
+        assert_eq!(issues[1].message, "'graphics' must be an array, found an object");
+    }
+
+    #[test]
+    fn reports_missing_empty_and_mistyped_titles() {
+        let missing = issues_of(json!({ "collection": {}, "graphics": [] }));
+        assert_single_issue(&missing, "$.collection.title", "Missing required 'title' field");
+
+        let empty = issues_of(json!({ "collection": { "title": "  " }, "graphics": [] }));
+        assert_single_issue(&empty, "$.collection.title", "'title' must not be empty");
+
+        let mistyped = graphic_issues(json!([{ "asset": "/1.png", "title": 1 }]));
+        assert_single_issue(&mistyped, "$.graphics[0].title", "'title' must be a string, found a number");
+    }
+
+    #[test]
+    fn reports_mistyped_optional_fields() {
+        let issues = issues_of(json!({ "collection": { "title": "C", "artist": true }, "graphics": [] }));
+        assert_single_issue(&issues, "$.collection.artist", "'artist' must be a string, found a boolean");
+
+        let issues = graphic_issues(json!([{ "asset": "/1.png", "title": "One", "description": null }]));
+        assert_single_issue(&issues, "$.graphics[0].description", "found null");
+    }
+
+    #[test]
+    fn reports_non_object_graphics() {
+        assert_single_issue(&graphic_issues(json!([7])), "$.graphics[0]", "Graphic must be an object");
+    }
+
+    #[test]
+    fn reports_duplicate_asset_paths() {
+        let issues = graphic_issues(json!([
+            { "asset": "/1.png", "title": "One" },
+            { "asset": "/1.png", "title": "Again" }
+        ]));
+        assert_single_issue(
+            &issues,
+            "$.graphics[1].asset",
+            "Duplicate asset path '/1.png' (first used at $.graphics[0].asset)",
+        );
+    }
+
+    #[test]
+    fn reports_invalid_media_types() {
+        let issues = graphic_issues(json!([{ "asset": "/1.png", "title": "One", "media_type": "png" }]));
+        assert_single_issue(&issues, "$.graphics[0].media_type", "'png' is not a valid media type");
+
+        let issues = graphic_issues(json!([{ "asset": "/1.png", "title": "One", "media_type": 1 }]));
+        assert_single_issue(&issues, "$.graphics[0].media_type", "must be a string");
+    }
+
+    #[test]
+    fn reports_invalid_tags() {
+        let issues = issues_of(json!({ "collection": { "title": "C", "tags": "art" }, "graphics": [] }));
+        assert_single_issue(&issues, "$.collection.tags", "'tags' must be an array, found a string");
+
+        let issues = graphic_issues(json!([{ "asset": "/1.png", "title": "One", "tags": ["ok", " ", 3] }]));
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].path, "$.graphics[0].tags[1]");
+        assert_eq!(issues[0].message, "Tag must not be empty");
+        assert_eq!(issues[1].path, "$.graphics[0].tags[2]");
+        assert_eq!(issues[1].message, "Tag must be a string");
+
+        let too_many: Vec<String> = (0..=MAX_TAGS).map(|i| format!("tag {}", i)).collect();
+        let issues = graphic_issues(json!([{ "asset": "/1.png", "title": "One", "tags": too_many }]));
+        assert_single_issue(&issues, "$.graphics[0].tags", "Too many tags");
+    }
+
+    #[test]
+    fn reports_invalid_attributes() {
+        let issues = graphic_issues(json!([{ "asset": "/1.png", "title": "One", "attributes": {} }]));
+        assert_single_issue(&issues, "$.graphics[0].attributes", "'attributes' must be an array");
+
+        let issues = graphic_issues(json!([{
+            "asset": "/1.png",
+            "title": "One",
+            "attributes": [
+                { "key": "Size", "value": 1 },
+                { "key": "Size", "value": 2 },
+                { "value": 3 }
+            ]
+        }]));
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].path, "$.graphics[0].attributes[1].key");
+        assert_eq!(issues[0].message, "Duplicate attribute key 'Size'");
+        assert_eq!(issues[1].path, "$.graphics[0].attributes[2]");
+        assert_eq!(issues[1].message, "Missing required 'key' field in attribute");
+
+        let too_many: Vec<Value> = (0..=MAX_ATTRIBUTES_PER_GRAPHIC)
+            .map(|i| json!({ "key": format!("k{}", i), "value": i }))
+            .collect();
+        let issues = graphic_issues(json!([{ "asset": "/1.png", "title": "One", "attributes": too_many }]));
+        assert_single_issue(&issues, "$.graphics[0].attributes", "Too many attributes");
+    }
+
+    #[test]
+    fn reports_every_issue_in_one_pass() {
+        let metadata = json!({
+            "collection": { "title": "", "tags": [""] },
+            "graphics": [
+                { "asset": "/1.png" },
+                { "asset": "/1.png", "title": "Two", "media_type": "png" },
+                "three"
+            ]
+        })
+        .to_string();
+        let (issues, graphic_count) = validate_metadata(&metadata);
+        let paths: Vec<&str> = issues.iter().map(|issue| issue.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "$.collection.title",
+                "$.collection.tags[0]",
+                "$.graphics[0].title",
+                "$.graphics[1].asset",
+                "$.graphics[1].media_type",
+                "$.graphics[2]",
+            ]
+        );
+        assert_eq!(graphic_count, 3);
+
+        let error = check_metadata(&metadata).unwrap_err();
+        assert!(error.starts_with("Invalid metadata: $.collection.title: "), "{}", error);
+        assert_eq!(error.matches("; ").count(), issues.len() - 1);
+    }
+}
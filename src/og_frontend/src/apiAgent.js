@@ -45,6 +45,53 @@ export async function fetchCollections() {
   }
 }
 
+/**
+ * Returns the value of a Candid `Result`, throwing if the canister returned an error variant.
+ */
+function unwrapResult(result) {
+  if ('Err' in result) {
+    const [code] = Object.keys(result.Err);
+    throw new Error(`${code}: ${result.Err[code].reason}`);
+  }
+  return result.Ok;
+}
+
+/**
+ * Converts a paginated Candid response into plain JavaScript values,
+ * mapping each item with `mapItem` (IDs are converted to numbers by default).
+ * Throws if the canister returned an error variant.
+ */
+function unwrapPage(result, mapItem = Number) {
+  const page = unwrapResult(result);
+  return {
+    items: page.items.map(item => mapItem(item)),
+    nextCursor: page.next_cursor.length ? Number(page.next_cursor[0]) : null,
+    total: Number(page.total),
+  };
+}
+
+/**
+ * Fetch one page of registered collection IDs.
+ * @param {number|null} cursor - The nextCursor of the previous page, or null for the first page.
+ * @param {number} limit - Maximum number of IDs to return.
+ * @param {'Registered'|'Recent'|'Title'} order - Order of the collections, applied by the canister across all pages.
+ */
+export async function fetchCollectionsPage(cursor = null, limit = 12, order = 'Registered') {
+  try {
+    const result = await ogBackendActor.fetch_collections_page(
+      {
+        cursor: cursor === null ? [] : [BigInt(cursor)],
+        limit: [BigInt(limit)],
+      },
+      [{ [order]: null }],
+    );
+    return unwrapPage(result);
+  } catch (err) {
+    console.error('fetchCollectionsPage error:', err);
+    throw err;
+  }
+}
+
 /**
  * Fetch the details of a single collection by its ID.
  * @param {number} collectionId
@@ -73,6 +120,102 @@ export async function fetchGraphics(collectionId) {
   }
 }
 
+/**
+ * Converts an IndexedGraphic Candid record into the same shape as the JSON text endpoints return.
+ */
+function normalizeGraphic(graphic) {
+  return {
+    ...graphic,
+    ogid: Number(graphic.ogid),
+    collection_id: Number(graphic.collection_id),
+    description: graphic.description.length ? graphic.description[0] : null,
+    update_timestamp: graphic.update_timestamp.length ? graphic.update_timestamp[0] : null,
+    attributes: graphic.attributes.length ? graphic.attributes[0] : [],
+    tags: graphic.tags.length ? graphic.tags[0] : [],
+    media_type: graphic.media_type.length ? graphic.media_type[0] : null,
+    width: graphic.width.length ? graphic.width[0] : null,
+    height: graphic.height.length ? graphic.height[0] : null,
+    byte_size: graphic.byte_size.length ? Number(graphic.byte_size[0]) : null,
+    content_hash: graphic.content_hash.length ? graphic.content_hash[0] : null,
+    canister_id: graphic.canister_id.toText(),
+  };
+}
+
+/**
+ * Fetch one page of full graphic records for a given collection.
+ * @param {number} collectionId
+ * @param {number|null} cursor - The nextCursor of the previous page, or null for the first page.
+ * @param {number} limit - Maximum number of graphics to return.
+ */
+export async function fetchGraphicsDetails(collectionId, cursor = null, limit = 25) {
+  try {
+    const result = await ogBackendActor.fetch_graphics_details(BigInt(collectionId), {
+      cursor: cursor === null ? [] : [BigInt(cursor)],
+      limit: [BigInt(limit)],
+    });
+    return unwrapPage(result, normalizeGraphic);
+  } catch (err) {
+    console.error('fetchGraphicsDetails error:', err);
+    throw err;
+  }
+}
+
+/**
+ * Fetch full details for a set of graphics in a single call.
+ * Unregistered OGIDs are omitted from the result.
+ * @param {number[]} ogids
+ */
+export async function fetchGraphicsByIds(ogids) {
+  try {
+    const result = await ogBackendActor.fetch_graphics_by_ids(ogids.map(BigInt));
+    return unwrapResult(result).map(normalizeGraphic);
+  } catch (err) {
+    console.error('fetchGraphicsByIds error:', err);
+    throw err;
+  }
+}
+
+/**
+ * Fetch every tag in use, with the number of collections and graphics carrying it.
+ */
+export async function fetchTags() {
+  try {
+    const result = await ogBackendActor.fetch_tags();
+    return unwrapResult(result).map(t => ({
+      tag: t.tag,
+      collectionCount: Number(t.collection_count),
+      graphicCount: Number(t.graphic_count),
+    }));
+  } catch (err) {
+    console.error('fetchTags error:', err);
+    throw err;
+  }
+}
+
+/**
+ * Fetch one page of the collections and graphics carrying a tag.
+ * Items are { kind: 'Collection' | 'Graphic', id, collectionId }.
+ * @param {string} tag
+ * @param {number|null} cursor - The nextCursor of the previous page, or null for the first page.
+ * @param {number} limit - Maximum number of records to return.
+ */
+export async function fetchByTag(tag, cursor = null, limit = 25) {
+  try {
+    const result = await ogBackendActor.fetch_by_tag(tag, {
+      cursor: cursor === null ? [] : [BigInt(cursor)],
+      limit: [BigInt(limit)],
+    });
+    return unwrapPage(result, r => ({
+      kind: Object.keys(r.kind)[0],
+      id: Number(r.id),
+      collectionId: Number(r.collection_id),
+    }));
+  } catch (err) {
+    console.error('fetchByTag error:', err);
+    throw err;
+  }
+}
+
 /**
  * Fetch full details for a specific graphic given its OGID.
  * @param {number} ogid
@@ -113,6 +256,25 @@ export async function retrieveAsset(ogid) {
   try {
     // Get graphic details (includes asset path and asset canister id)
     const graphic = await fetchGraphic(ogid);
+    return await retrieveGraphicAsset(graphic);
+  } catch (err) {
+    console.error('retrieveAsset error:', err);
+    throw err;
+  }
+}
+
+// Media type assumed for graphics registered before the registry recorded one.
+const FALLBACK_MEDIA_TYPE = 'image/png';
+
+/**
+ * Retrieves an asset image for graphic details that have already been fetched,
+ * avoiding a second round-trip to the registry.
+ *
+ * @param {object} graphic - Graphic details including `asset`, `canister_id` and `media_type`.
+ * @returns {Promise<string>} - A data URL for the retrieved image.
+ */
+export async function retrieveGraphicAsset(graphic) {
+  try {
     const asset = graphic.asset;
     const assetCanisterId = graphic.canister_id;
     
@@ -130,10 +292,11 @@ export async function retrieveAsset(ogid) {
     const binaryString = Array.from(uint8Array, byte => String.fromCharCode(byte)).join('');
     const base64String = btoa(binaryString);
     
-    // Return as a data URL (assuming PNG format; adjust if needed)
-    return `data:image/png;base64,${base64String}`;
+    // Return as a data URL of the media type recorded by the registry.
+    const mediaType = graphic.media_type || FALLBACK_MEDIA_TYPE;
+    return `data:${mediaType};base64,${base64String}`;
   } catch (err) {
-    console.error('retrieveAsset error:', err);
+    console.error('retrieveGraphicAsset error:', err);
     throw err;
   }
 }
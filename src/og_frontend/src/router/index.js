@@ -4,6 +4,7 @@ import About from '../views/About.vue';
 import Work from '../views/Work.vue';
 import Collection from '../views/Collection.vue';
 import Graphic from '../views/Graphic.vue'; // <-- Import new view
+import Tags from '../views/Tags.vue';
 import Roadmap from '../views/Roadmap.vue';
 import Contact from '../views/Contact.vue';
 
@@ -13,6 +14,7 @@ const routes = [
   { path: '/work', name: 'Work', component: Work },
   { path: '/collection/:id', name: 'Collection', component: Collection },
   { path: '/graphic/:collectionId/:graphicId', name: 'Graphic', component: Graphic }, // New route
+  { path: '/tags/:tag?', name: 'Tags', component: Tags },
   { path: '/roadmap', name: 'Roadmap', component: Roadmap },
   { path: '/contact', name: 'Contact', component: Contact },
 ];
@@ -1,63 +1,140 @@
 // File: src/og_frontend/src/store/workStore.js
 import { defineStore } from 'pinia';
-import { fetchCollections, fetchCollection, fetchGraphics, retrieveAsset } from '@/apiAgent.js';
+import {
+  fetchCollectionsPage,
+  fetchCollection,
+  fetchGraphics,
+  fetchGraphicsByIds,
+  retrieveGraphicAsset,
+} from '@/apiAgent.js';
+
+// Number of collections requested per page.
+const PAGE_SIZE = 12;
+
+// Maximum number of OGIDs the registry accepts in one fetch_graphics_by_ids call.
+const MAX_GRAPHICS_PER_BATCH = 100;
+
+/**
+ * Loads the details and graphic OGIDs of a single collection.
+ * Returns null if the collection could not be loaded.
+ */
+async function loadCollection(id) {
+  try {
+    const details = await fetchCollection(id);
+    const graphics = await fetchGraphics(id);
+    return {
+      id,
+      title: details.title || `Collection ${id}`,
+      description: details.description || '',
+      artist: details.artist || '',
+      external_link: details.external_link || '',
+      registration_timestamp: details.registration_timestamp || '',
+      update_timestamp: details.update_timestamp || '', // new field
+      graphics,
+      imageUrl: '',
+    };
+  } catch (err) {
+    console.error("Error loading collection", id, err);
+    return null;
+  }
+}
 
 export const useWorkStore = defineStore('work', {
   state: () => ({
     collections: [],
     loading: false,
+    loadingMore: false,
+    nextCursor: null,
+    total: 0,
+    // Order requested from the canister: 'Recent' (newest registration first) or 'Title'.
+    order: 'Recent',
   }),
+  getters: {
+    hasMore: (state) => state.nextCursor !== null,
+  },
   actions: {
     async loadCollections() {
       // If collections are already loaded, do nothing.
       if (this.collections.length > 0) return;
       this.loading = true;
       try {
-        const collectionIds = await fetchCollections();
-        const promises = collectionIds.map(async (id) => {
-          try {
-            const details = await fetchCollection(id);
-            const graphics = await fetchGraphics(id);
-            return {
-              id,
-              title: details.title || `Collection ${id}`,
-              description: details.description || '',
-              artist: details.artist || '',
-              external_link: details.external_link || '',
-              registration_timestamp: details.registration_timestamp || '',
-              update_timestamp: details.update_timestamp || '', // new field
-              graphics,
-              imageUrl: '',
-            };
-          } catch (err) {
-            console.error("Error loading collection", id, err);
-            return null;
-          }
-        });
-        const results = await Promise.all(promises);
-        this.collections = results.filter(c => c !== null);
+        await this.loadNextPage();
       } catch (err) {
         console.error("Failed to load collections", err);
       } finally {
         this.loading = false;
       }
     },
-    async updateArtwork() {
+    // Returns false if the page could not be loaded, so callers can stop asking for more.
+    async loadMoreCollections() {
+      // Ignore the request while a page is in flight or once everything has been loaded.
+      if (this.loadingMore || !this.hasMore) return false;
+      this.loadingMore = true;
+      try {
+        const firstNew = this.collections.length;
+        await this.loadNextPage();
+        await this.updateArtwork(firstNew);
+        return true;
+      } catch (err) {
+        console.error("Failed to load more collections", err);
+        return false;
+      } finally {
+        this.loadingMore = false;
+      }
+    },
+    // Pages are ordered by the canister, so changing the order starts over from the first page.
+    async setOrder(order) {
+      if (order === this.order) return;
+      this.order = order;
+      this.collections = [];
+      this.nextCursor = null;
+      this.total = 0;
+      await this.loadCollections();
+      await this.updateArtwork();
+    },
+    async loadNextPage() {
+      const order = this.order;
+      const page = await fetchCollectionsPage(this.nextCursor, PAGE_SIZE, order);
+      // Drop pages of an order that was replaced while they were loading.
+      if (order !== this.order) return;
+      const results = await Promise.all(page.items.map(loadCollection));
+      this.collections.push(...results.filter(c => c !== null));
+      this.nextCursor = page.nextCursor;
+      this.total = page.total;
+    },
+    // Picks a random graphic per collection, fetches their details in batches
+    // and then retrieves each asset directly from its asset canister.
+    async updateArtwork(from = 0) {
+      const picks = [];
+      this.collections.slice(from).forEach((collection, offset) => {
+        if (collection.graphics && collection.graphics.length > 0) {
+          const randomIndex = Math.floor(Math.random() * collection.graphics.length);
+          picks.push({ index: from + offset, graphicId: collection.graphics[randomIndex] });
+        }
+      });
+
+      const graphics = new Map();
+      for (let start = 0; start < picks.length; start += MAX_GRAPHICS_PER_BATCH) {
+        const ids = picks.slice(start, start + MAX_GRAPHICS_PER_BATCH).map(p => p.graphicId);
+        try {
+          const batch = await fetchGraphicsByIds(ids);
+          batch.forEach(graphic => graphics.set(graphic.ogid, graphic));
+        } catch (err) {
+          console.error("Error fetching graphics for artwork", err);
+        }
+      }
+
       await Promise.all(
-        this.collections.map(async (collection, index) => {
-          if (collection.graphics && collection.graphics.length > 0) {
-            const randomIndex = Math.floor(Math.random() * collection.graphics.length);
-            const graphicId = collection.graphics[randomIndex];
-            try {
-              const imageUrl = await retrieveAsset(graphicId);
-              this.collections[index].imageUrl = imageUrl;
-            } catch (err) {
-              console.error("Error retrieving asset for collection", collection.id, err);
-            }
+        picks.map(async ({ index, graphicId }) => {
+          const graphic = graphics.get(Number(graphicId));
+          if (!graphic) return;
+          try {
+            this.collections[index].imageUrl = await retrieveGraphicAsset(graphic);
+          } catch (err) {
+            console.error("Error retrieving asset for collection", this.collections[index].id, err);
           }
         })
       );
     },
   },
 });
-